@@ -0,0 +1,99 @@
+// Per-frame cost of updating the field sprites, all cells vs. only the changed ones, for boards larger than the
+// playfield. Drives the same DirtyCells::changed the renderers use; the bevy side (looking up the cell entity and
+// writing its sprite index) is stood in for by a plain vector. Run with `cargo bench --bench field_update`.
+
+#[path = "../src/dirty.rs"]
+#[allow(dead_code)]
+mod dirty;
+
+use dirty::DirtyCells;
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+
+const FRAMES: usize = 100_000;
+// a line clear every 120 frames, animated for 30 frames like the line transition
+const CLEAR_EVERY: usize = 120;
+const CLEAR_FRAMES: usize = 30;
+
+// stands in for the field entities and their sprite indices
+struct Sprites {
+    width: usize,
+    index: Vec<u32>,
+}
+
+impl Sprites {
+    fn update(&mut self, board: &[u8], x: usize, y: usize, clearing: bool) {
+        let i = y * self.width + x;
+        self.index[i] = if clearing { 16 } else { board[i] as u32 % 16 };
+    }
+}
+
+// a piece falling through a half filled board: 4 cells erased and 4 cells drawn per frame
+fn simulate_frame(
+    board: &mut [u8],
+    dirty: &mut DirtyCells,
+    width: usize,
+    height: usize,
+    frame: usize,
+) {
+    let x = frame % (width - 3);
+    let y = height / 2 + frame % (height / 2);
+    let prev_y = height / 2 + (frame + height / 2 - 1) % (height / 2);
+    for dx in 0..4 {
+        board[prev_y * width + x + dx] = 0;
+        dirty.mark(x + dx, prev_y);
+        board[y * width + x + dx] = 8;
+        dirty.mark(x + dx, y);
+    }
+}
+
+fn bench(width: usize, height: usize, only_changed: bool) -> Duration {
+    let mut board = vec![0u8; width * height];
+    for (i, c) in board.iter_mut().enumerate().take(width * height / 2) {
+        *c = (i % 7 + 3) as u8;
+    }
+    let mut dirty = DirtyCells::new(width, height);
+    let mut sprites = Sprites {
+        width,
+        index: vec![0; width * height],
+    };
+
+    let start = Instant::now();
+    for frame in 0..FRAMES {
+        simulate_frame(&mut board, &mut dirty, width, height, frame);
+        let clearing = if frame % CLEAR_EVERY < CLEAR_FRAMES {
+            (0..4).collect::<HashSet<_>>()
+        } else {
+            HashSet::new()
+        };
+        if only_changed {
+            for (x, y) in dirty.changed(&clearing) {
+                sprites.update(&board, x, y, clearing.contains(&y));
+            }
+        } else {
+            for y in 0..height {
+                for x in 0..width {
+                    sprites.update(&board, x, y, clearing.contains(&y));
+                }
+            }
+        }
+        dirty.clear();
+    }
+    // keep the optimizer from dropping the sprite updates
+    assert!(sprites.index.iter().any(|i| *i != 0));
+    start.elapsed() / FRAMES as u32
+}
+
+fn main() {
+    for (width, height) in [(10, 40), (20, 40)].iter() {
+        println!(
+            "{}x{}: all cells {:?} / frame, changed cells {:?} / frame",
+            width,
+            height,
+            bench(*width, *height, false),
+            bench(*width, *height, true)
+        );
+    }
+}
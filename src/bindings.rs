@@ -0,0 +1,437 @@
+use super::State;
+use bevy::prelude::*;
+
+const BINDINGS_FILE: &str = "bevris_keys.txt";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Left,
+    Right,
+    SoftDrop,
+    HardDrop,
+    RotateCw,
+    RotateCcw,
+    Rotate180,
+    Hold,
+    Pause,
+}
+
+pub const ACTIONS: [Action; 9] = [
+    Action::Left,
+    Action::Right,
+    Action::SoftDrop,
+    Action::HardDrop,
+    Action::RotateCw,
+    Action::RotateCcw,
+    Action::Rotate180,
+    Action::Hold,
+    Action::Pause,
+];
+
+impl Action {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::SoftDrop => "soft_drop",
+            Action::HardDrop => "hard_drop",
+            Action::RotateCw => "rotate_cw",
+            Action::RotateCcw => "rotate_ccw",
+            Action::Rotate180 => "rotate_180",
+            Action::Hold => "hold",
+            Action::Pause => "pause",
+        }
+    }
+
+    fn default_keys(&self) -> Vec<KeyCode> {
+        match self {
+            Action::Left => vec![KeyCode::Left],
+            Action::Right => vec![KeyCode::Right],
+            Action::SoftDrop => vec![KeyCode::Down],
+            Action::HardDrop => vec![KeyCode::X],
+            Action::RotateCw => vec![KeyCode::Up, KeyCode::Space],
+            Action::RotateCcw => vec![KeyCode::Z],
+            Action::Rotate180 => vec![KeyCode::A],
+            Action::Hold => vec![KeyCode::C, KeyCode::LShift],
+            Action::Pause => vec![KeyCode::P, KeyCode::Escape],
+        }
+    }
+}
+
+// the keys that can be bound, written to the bindings file by their names (e.g. `LShift`)
+const KEYS: [KeyCode; 68] = [
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+    KeyCode::Key0,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+    KeyCode::Numpad0,
+    KeyCode::Numpad1,
+    KeyCode::Numpad2,
+    KeyCode::Numpad3,
+    KeyCode::Numpad4,
+    KeyCode::Numpad5,
+    KeyCode::Numpad6,
+    KeyCode::Numpad7,
+    KeyCode::Numpad8,
+    KeyCode::Numpad9,
+    KeyCode::Left,
+    KeyCode::Right,
+    KeyCode::Up,
+    KeyCode::Down,
+    KeyCode::Space,
+    KeyCode::Return,
+    KeyCode::Escape,
+    KeyCode::Tab,
+    KeyCode::Back,
+    KeyCode::Insert,
+    KeyCode::Delete,
+    KeyCode::Home,
+    KeyCode::End,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+    KeyCode::LShift,
+    KeyCode::RShift,
+    KeyCode::LControl,
+    KeyCode::RControl,
+    KeyCode::LAlt,
+    KeyCode::RAlt,
+    KeyCode::Comma,
+];
+
+fn key_name(key: KeyCode) -> String {
+    format!("{:?}", key)
+}
+
+fn parse_key(name: &str) -> Option<KeyCode> {
+    KEYS.iter().cloned().find(|key| key_name(*key) == name)
+}
+
+// Keys of each action, any number per action. Stored as one `<action> <key> <key> ..` line per action, e.g.
+// `hold C LShift`. Actions missing from the file keep their default keys, as does pause if it has none (the
+// game couldn't be paused anymore).
+pub struct Bindings {
+    keys: Vec<(Action, Vec<KeyCode>)>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings {
+            keys: ACTIONS
+                .iter()
+                .map(|action| (*action, action.default_keys()))
+                .collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for Bindings {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (action, keys) in self.keys.iter() {
+            let keys = keys.iter().map(|key| key_name(*key)).collect::<Vec<_>>();
+            writeln!(f, "{} {}", action.name(), keys.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+impl Bindings {
+    pub fn load() -> Self {
+        Bindings::parse(&std::fs::read_to_string(BINDINGS_FILE).unwrap_or_default())
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        std::fs::write(BINDINGS_FILE, self.to_string())
+    }
+
+    // the contents of the bindings file, problems are reported and skipped
+    pub fn parse(i: &str) -> Self {
+        let mut bindings = Bindings::default();
+        for line in i.lines() {
+            let mut tokens = line.split_whitespace();
+            let action = match tokens
+                .next()
+                .and_then(|name| ACTIONS.iter().find(|action| action.name() == name))
+            {
+                Some(action) => *action,
+                None => continue,
+            };
+            let keys = tokens
+                .filter_map(|name| {
+                    let key = parse_key(name);
+                    if key.is_none() {
+                        println!("unknown key in {}: {}", BINDINGS_FILE, name);
+                    }
+                    key
+                })
+                .collect::<Vec<_>>();
+            if action == Action::Pause && keys.is_empty() {
+                println!("no pause key in {}, using the default keys", BINDINGS_FILE);
+                continue;
+            }
+            bindings.set(action, keys);
+        }
+        for key in KEYS.iter() {
+            let actions = bindings.actions(*key);
+            if actions.len() > 1 {
+                let names = actions
+                    .iter()
+                    .map(|action| action.name())
+                    .collect::<Vec<_>>();
+                println!(
+                    "{} is bound to several actions: {}",
+                    key_name(*key),
+                    names.join(", ")
+                );
+            }
+        }
+        bindings
+    }
+
+    pub fn keys(&self, action: Action) -> &[KeyCode] {
+        self.keys
+            .iter()
+            .find(|(a, _)| *a == action)
+            .map_or(&[], |(_, keys)| keys.as_slice())
+    }
+
+    fn set(&mut self, action: Action, keys: Vec<KeyCode>) {
+        for (a, k) in self.keys.iter_mut() {
+            if *a == action {
+                *k = keys;
+                return;
+            }
+        }
+    }
+
+    // a key can only be bound to one action
+    fn add(&mut self, action: Action, key: KeyCode) -> Result<(), String> {
+        if let Some(other) = self.actions(key).iter().find(|a| **a != action) {
+            return Err(format!("{} is bound to {}", key_name(key), other.name()));
+        }
+        let mut keys = self.keys(action).to_vec();
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+        self.set(action, keys);
+        Ok(())
+    }
+
+    fn remove_all(&mut self, action: Action) -> Result<(), String> {
+        if action == Action::Pause {
+            return Err("pause needs a key".to_string());
+        }
+        self.set(action, Vec::new());
+        Ok(())
+    }
+
+    // the actions a key is bound to
+    pub fn actions(&self, key: KeyCode) -> Vec<Action> {
+        self.keys
+            .iter()
+            .filter(|(_, keys)| keys.contains(&key))
+            .map(|(action, _)| *action)
+            .collect()
+    }
+
+    pub fn pressed(&self, input: &Input<KeyCode>, action: Action) -> bool {
+        self.keys(action).iter().any(|key| input.pressed(*key))
+    }
+
+    pub fn just_pressed(&self, input: &Input<KeyCode>, action: Action) -> bool {
+        self.keys(action).iter().any(|key| input.just_pressed(*key))
+    }
+}
+
+// rebinding screen, opened with F4 while the game is paused
+#[derive(Default)]
+pub struct Rebinding {
+    pub open: bool,
+    selected: usize,
+    // the next key press is bound to the selected action
+    listening: bool,
+    // why the last change was refused
+    error: Option<String>,
+}
+
+struct RebindingText;
+
+fn setup_rebinding_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(TextComponents {
+            text: Text {
+                font: asset_server
+                    .load("assets/fonts/FiraMono-Medium.ttf")
+                    .unwrap(),
+                value: String::new(),
+                style: TextStyle {
+                    color: Color::rgb(0.2, 0.2, 0.8),
+                    font_size: 30.0,
+                },
+            },
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(100.0),
+                    left: Val::Px(5.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with(RebindingText);
+}
+
+// Up / Down select an action, Return adds a key to it (the next key pressed, Escape cancels), Back removes its
+// keys. Keys bound to another action and removing the pause keys are refused. The bindings are saved when the
+// screen is closed.
+fn rebinding_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    state: Res<State>,
+    mut rebinding: ResMut<Rebinding>,
+    mut bindings: ResMut<Bindings>,
+    mut text_query: Query<(&RebindingText, &mut Text)>,
+) {
+    if rebinding.listening {
+        if keyboard_input.just_pressed(KeyCode::Escape) {
+            rebinding.listening = false;
+        } else if let Some(key) = keyboard_input
+            .get_just_pressed()
+            .find(|key| KEYS.contains(key))
+        {
+            rebinding.error = bindings.add(ACTIONS[rebinding.selected], *key).err();
+            rebinding.listening = false;
+        }
+    } else if keyboard_input.just_pressed(KeyCode::F4) && (state.paused || rebinding.open) {
+        rebinding.open = !rebinding.open;
+        rebinding.error = None;
+        if !rebinding.open {
+            if let Err(err) = bindings.save() {
+                println!("failed to save key bindings: {}", err);
+            }
+        }
+    } else if rebinding.open {
+        if keyboard_input.just_pressed(KeyCode::Up) && rebinding.selected > 0 {
+            rebinding.selected -= 1;
+        }
+        if keyboard_input.just_pressed(KeyCode::Down) && rebinding.selected + 1 < ACTIONS.len() {
+            rebinding.selected += 1;
+        }
+        if keyboard_input.just_pressed(KeyCode::Return) {
+            rebinding.listening = true;
+            rebinding.error = None;
+        }
+        if keyboard_input.just_pressed(KeyCode::Back) {
+            rebinding.error = bindings.remove_all(ACTIONS[rebinding.selected]).err();
+        }
+    }
+
+    let value = if rebinding.open {
+        ACTIONS
+            .iter()
+            .enumerate()
+            .map(|(i, action)| {
+                let cursor = if i == rebinding.selected { ">" } else { " " };
+                let keys = if i == rebinding.selected && rebinding.listening {
+                    "press a key".to_string()
+                } else {
+                    let keys = bindings.keys(*action).iter().map(|key| key_name(*key));
+                    keys.collect::<Vec<_>>().join(" ")
+                };
+                format!("{} {}: {}", cursor, action.name(), keys)
+            })
+            .chain(rebinding.error.clone())
+            .collect::<Vec<_>>()
+            .join("\n")
+    } else {
+        String::new()
+    };
+    for (_, mut text) in &mut text_query.iter() {
+        text.value = value.clone();
+    }
+}
+
+pub struct BindingsPlugin;
+
+impl Plugin for BindingsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_resource(Bindings::load())
+            .init_resource::<Rebinding>()
+            .add_startup_system(setup_rebinding_text.system())
+            .add_system(rebinding_system.system());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut bindings = Bindings::default();
+        bindings.add(Action::Hold, KeyCode::Tab).unwrap();
+        bindings.remove_all(Action::Rotate180).unwrap();
+        let parsed = Bindings::parse(&bindings.to_string());
+        assert_eq!(parsed.keys, bindings.keys);
+    }
+
+    #[test]
+    fn parse() {
+        let bindings = Bindings::parse("hold Q LShift Foo\nrotate_180\nunknown A\n");
+        assert_eq!(bindings.keys(Action::Hold), &[KeyCode::Q, KeyCode::LShift]);
+        assert!(bindings.keys(Action::Rotate180).is_empty());
+        // missing actions keep their defaults
+        assert_eq!(bindings.keys(Action::Left), &[KeyCode::Left]);
+        assert_eq!(bindings.to_string().lines().count(), ACTIONS.len());
+    }
+
+    #[test]
+    fn pause_keeps_a_key() {
+        let mut bindings = Bindings::parse("pause\n");
+        assert_eq!(bindings.keys(Action::Pause), &[KeyCode::P, KeyCode::Escape]);
+        assert!(bindings.remove_all(Action::Pause).is_err());
+        assert!(!bindings.keys(Action::Pause).is_empty());
+    }
+
+    #[test]
+    fn conflicts() {
+        let mut bindings = Bindings::default();
+        assert!(bindings.add(Action::Hold, KeyCode::X).is_err());
+        assert_eq!(bindings.actions(KeyCode::X), vec![Action::HardDrop]);
+        // adding a key twice is fine
+        assert!(bindings.add(Action::HardDrop, KeyCode::X).is_ok());
+        assert_eq!(bindings.keys(Action::HardDrop), &[KeyCode::X]);
+    }
+}
@@ -0,0 +1,174 @@
+use super::{
+    field::Renderer,
+    layout::{Layout, CELL},
+    theme::Theme,
+};
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+// the characters of the font atlas, 16 per row. Lower case letters are drawn as upper case, everything else as space
+const CHARS: &str = " 0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ:/.-+!%(),>";
+const COLUMNS: usize = 16;
+// size of a character in the atlas and in world units
+const CHAR_PIXELS: f32 = 8.0;
+const CHAR_SIZE: f32 = CELL / 2.0;
+const LINE_HEIGHT: f32 = CHAR_SIZE * 1.25;
+
+struct Glyph;
+
+fn glyph_index(c: char) -> u32 {
+    CHARS.find(c.to_ascii_uppercase()).unwrap_or(0) as u32
+}
+
+fn glyph_offset(row: usize, column: usize) -> Vec3 {
+    Vec3::new(
+        (column as f32 + 0.5) * CHAR_SIZE,
+        -(row as f32 * LINE_HEIGHT + CHAR_SIZE / 2.0),
+        2.0,
+    )
+}
+
+// the glyph sprites of a text entity, one per character position by row and column. Positions past the end of
+// the current text show the (empty) space glyph.
+struct TextGlyphs {
+    pos: Vec3,
+    value: String,
+    glyphs: Vec<Vec<Entity>>,
+}
+
+#[derive(Default)]
+struct BitmapFont {
+    path: Option<&'static str>,
+    atlas: Handle<TextureAtlas>,
+    texts: HashMap<Entity, TextGlyphs>,
+}
+
+impl BitmapFont {
+    fn despawn(&mut self, commands: &mut Commands, text: Entity) {
+        if let Some(shown) = self.texts.remove(&text) {
+            for glyph in shown.glyphs.iter().flatten() {
+                commands.despawn(*glyph);
+            }
+        }
+    }
+}
+
+// Draws the text of all text entities (score, stats, callouts, menus) with the font atlas of the theme when the
+// textured renderer is used, and hides the ui text meanwhile. The glyph sprites of each text are kept and only
+// get new atlas indices when the text changes (the timers change every frame).
+fn bitmap_text_system(
+    mut commands: Commands,
+    renderer: Res<Renderer>,
+    theme: Res<Theme>,
+    layout: Res<Layout>,
+    asset_server: Res<AssetServer>,
+    mut font: Local<BitmapFont>,
+    mut textures: ResMut<Assets<Texture>>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    mut text_query: Query<(Entity, &Text, &Style, &mut Draw)>,
+    mut glyph_query: Query<(&Glyph, &mut TextureAtlasSprite, &mut Transform)>,
+) {
+    let path = match theme.font {
+        Some(path) if *renderer == Renderer::Textured => Some(path),
+        _ => None,
+    };
+    // another font (or none), the glyphs are rebuilt with the new atlas
+    if font.path != path {
+        let texts = font.texts.keys().cloned().collect::<Vec<_>>();
+        for text in texts {
+            font.despawn(&mut commands, text);
+        }
+        if let Some(path) = path {
+            let texture_handle = asset_server.load_sync(&mut textures, path).unwrap();
+            let texture = textures.get(&texture_handle).unwrap();
+            let rows = (CHARS.len() + COLUMNS - 1) / COLUMNS;
+            let texture_atlas =
+                TextureAtlas::from_grid(texture_handle, texture.size, COLUMNS, rows);
+            font.atlas = texture_atlases.add(texture_atlas);
+        }
+        font.path = path;
+    }
+
+    let mut texts = Vec::new();
+    for (entity, text, style, mut draw) in &mut text_query.iter() {
+        draw.is_visible = path.is_none();
+        if let (Val::Px(left), Val::Px(top)) = (style.position.left, style.position.top) {
+            texts.push((entity, layout.to_world((left, top)), text.value.clone()));
+        }
+    }
+    if path.is_none() {
+        return;
+    }
+    let gone = font
+        .texts
+        .keys()
+        .filter(|text| !texts.iter().any(|(entity, _, _)| entity == *text))
+        .cloned()
+        .collect::<Vec<_>>();
+    for text in gone {
+        font.despawn(&mut commands, text);
+    }
+
+    let atlas = font.atlas;
+    for (entity, pos, value) in texts {
+        let shown = font.texts.entry(entity).or_insert_with(|| TextGlyphs {
+            pos,
+            value: String::new(),
+            glyphs: Vec::new(),
+        });
+        if shown.value == value && shown.pos == pos {
+            continue;
+        }
+        let lines = value
+            .lines()
+            .map(|line| line.chars().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        shown
+            .glyphs
+            .resize(lines.len().max(shown.glyphs.len()), Vec::new());
+        for (row, glyphs) in shown.glyphs.iter_mut().enumerate() {
+            let line = lines.get(row).map_or(&[][..], |line| line.as_slice());
+            for column in 0..line.len().max(glyphs.len()) {
+                let index = glyph_index(line.get(column).cloned().unwrap_or(' '));
+                let translation = pos + glyph_offset(row, column);
+                match glyphs.get(column) {
+                    Some(glyph) => {
+                        if let Ok(mut sprite) = glyph_query.get_mut::<TextureAtlasSprite>(*glyph) {
+                            sprite.index = index;
+                        }
+                        if shown.pos != pos {
+                            if let Ok(mut transform) = glyph_query.get_mut::<Transform>(*glyph) {
+                                transform.set_translation(translation);
+                            }
+                        }
+                    }
+                    None => {
+                        commands
+                            .spawn(SpriteSheetComponents {
+                                texture_atlas: atlas,
+                                transform: Transform::from_scale(CHAR_SIZE / CHAR_PIXELS)
+                                    .with_translation(translation),
+                                sprite: TextureAtlasSprite {
+                                    index,
+                                    ..Default::default()
+                                },
+                                ..Default::default()
+                            })
+                            .with(Glyph);
+                        glyphs.push(commands.current_entity().unwrap());
+                    }
+                }
+            }
+        }
+        shown.pos = pos;
+        shown.value = value;
+    }
+}
+
+pub struct BitmapFontPlugin;
+
+impl Plugin for BitmapFontPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(bitmap_text_system.system());
+    }
+}
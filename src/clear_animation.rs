@@ -0,0 +1,70 @@
+use super::field::Cell;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClearAnimation {
+    // the cells blow up one after another
+    Explode,
+    // the row blinks
+    Flash,
+    // the row slides out to the right
+    Slide,
+    // the cells vanish from left to right
+    Dissolve,
+}
+
+// what a cell of a row being cleared looks like, the renderers map this to their tiles / colors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClearedCell {
+    Cell(Cell),
+    Empty,
+    Flash,
+    // 0.0 ..= 1.0 through the explosion of the cell
+    Explode(f32),
+}
+
+impl ClearAnimation {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "explode" => Some(ClearAnimation::Explode),
+            "flash" => Some(ClearAnimation::Flash),
+            "slide" => Some(ClearAnimation::Slide),
+            "dissolve" => Some(ClearAnimation::Dissolve),
+            _ => None,
+        }
+    }
+
+    // progress of the line transition, 0.0 ..= 1.0. r is a random number per field cell (for staggering)
+    pub fn cell(&self, row: &[Cell; 10], x: usize, progress: f32, r: u32) -> ClearedCell {
+        match *self {
+            ClearAnimation::Explode => {
+                // up to a quarter of the transition of delay
+                let start = (r % 4) as f32 / 12.0;
+                if progress < start {
+                    ClearedCell::Cell(row[x])
+                } else {
+                    ClearedCell::Explode(((progress - start) / (1.0 - start)).min(1.0))
+                }
+            }
+            ClearAnimation::Flash => match (progress * 6.0) as u32 {
+                0 | 2 | 4 => ClearedCell::Flash,
+                1 | 3 => ClearedCell::Cell(row[x]),
+                _ => ClearedCell::Empty,
+            },
+            ClearAnimation::Slide => {
+                let shift = (progress * (row.len() + 1) as f32) as usize;
+                if x < shift {
+                    ClearedCell::Empty
+                } else {
+                    ClearedCell::Cell(row[x - shift])
+                }
+            }
+            ClearAnimation::Dissolve => {
+                if (x as f32) < progress * row.len() as f32 {
+                    ClearedCell::Empty
+                } else {
+                    ClearedCell::Cell(row[x])
+                }
+            }
+        }
+    }
+}
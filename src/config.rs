@@ -0,0 +1,33 @@
+const CONFIG_FILE: &str = "bevris.cfg";
+
+// Settings file with one `<key> <value>` line per setting, e.g. `renderer solid`. Command line flags take
+// precedence over the file.
+#[derive(Default)]
+pub struct Config {
+    entries: Vec<(String, String)>,
+}
+
+impl Config {
+    pub fn load() -> Self {
+        let entries = std::fs::read_to_string(CONFIG_FILE)
+            .unwrap_or_default()
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                let mut tokens = line.splitn(2, char::is_whitespace);
+                let key = tokens.next()?;
+                let value = tokens.next()?.trim();
+                Some((key.to_string(), value.to_string()))
+            })
+            .collect();
+        Config { entries }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(entry_key, _)| entry_key == key)
+            .map(|(_, value)| value.as_str())
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::HashSet;
+
+// Tracks which cells of a grid changed since the renderers last caught up, as one bit mask per row. Kept free
+// of any game / bevy types so that benches/field_update.rs can use it on its own.
+pub struct DirtyCells {
+    width: usize,
+    rows: Vec<u64>,
+}
+
+impl DirtyCells {
+    // everything starts out dirty, nothing has been drawn yet
+    pub fn new(width: usize, height: usize) -> Self {
+        assert!(width <= 64);
+        let mut dirty = DirtyCells {
+            width,
+            rows: vec![0; height],
+        };
+        dirty.mark_all();
+        dirty
+    }
+
+    pub fn mark(&mut self, x: usize, y: usize) {
+        self.rows[y] |= 1 << x;
+    }
+
+    pub fn mark_row(&mut self, y: usize) {
+        self.rows[y] = self.full_row();
+    }
+
+    pub fn mark_all(&mut self) {
+        let full_row = self.full_row();
+        for row in self.rows.iter_mut() {
+            *row = full_row;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for row in self.rows.iter_mut() {
+            *row = 0;
+        }
+    }
+
+    // (x, y) of all dirty cells
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.rows
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| **row != 0)
+            .flat_map(|(y, row)| {
+                // pops the lowest set bit
+                let mut row = *row;
+                std::iter::from_fn(move || {
+                    if row == 0 {
+                        return None;
+                    }
+                    let x = row.trailing_zeros() as usize;
+                    row &= row - 1;
+                    Some((x, y))
+                })
+            })
+    }
+
+    // the cells to redraw: the rows being cleared (they are animated) and the dirty cells
+    pub fn changed(&self, clearing: &HashSet<usize>) -> Vec<(usize, usize)> {
+        let mut cells = self
+            .iter()
+            .filter(|(_, y)| !clearing.contains(y))
+            .collect::<Vec<_>>();
+        for y in clearing.iter() {
+            cells.extend((0..self.width).map(|x| (x, *y)));
+        }
+        cells
+    }
+
+    fn full_row(&self) -> u64 {
+        if self.width == 64 {
+            !0
+        } else {
+            (1 << self.width) - 1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_cells() {
+        let mut dirty = DirtyCells::new(20, 40);
+        assert_eq!(dirty.iter().count(), 20 * 40);
+        dirty.clear();
+        dirty.mark(3, 4);
+        dirty.mark(19, 39);
+        dirty.mark(5, 1);
+        assert_eq!(
+            dirty.iter().collect::<Vec<_>>(),
+            vec![(5, 1), (3, 4), (19, 39)]
+        );
+        // rows being cleared are redrawn entirely, once
+        let clearing = [1].iter().cloned().collect();
+        let changed = dirty.changed(&clearing);
+        assert_eq!(changed.len(), 2 + 20);
+        assert!(changed.contains(&(0, 1)) && changed.contains(&(19, 39)));
+    }
+}
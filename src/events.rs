@@ -0,0 +1,103 @@
+use super::{Piece, PieceType, State};
+use bevy::prelude::*;
+
+// Gameplay events, e.g. for audio, effects or statistics. The simulation queues them in `State::events`
+// while running its ticks, they are sent as regular bevy events at the end of the frame.
+
+pub struct PieceSpawned {
+    pub piece_type: PieceType,
+}
+
+// dx != 0 for shifts, dy == -1 for gravity / soft drop
+pub struct PieceMoved {
+    pub piece_type: PieceType,
+    pub piece: Piece,
+    pub dx: i32,
+    pub dy: i32,
+    pub soft_drop: bool,
+}
+
+pub struct PieceRotated {
+    pub piece_type: PieceType,
+    pub piece: Piece,
+}
+
+pub struct PieceHeld {
+    pub held: PieceType,
+    // the piece that replaced the held one
+    pub piece_type: PieceType,
+}
+
+pub struct PieceLocked {
+    pub piece_type: PieceType,
+    pub piece: Piece,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Spin {
+    None,
+    TSpin,
+}
+
+pub struct LinesCleared {
+    pub rows: Vec<usize>,
+    pub spin: Spin,
+    pub combo: usize,
+    pub b2b: usize,
+    pub perfect_clear: bool,
+}
+
+pub struct LevelUp {
+    pub level: usize,
+}
+
+pub struct GameOver {
+    pub topped_out: bool,
+}
+
+pub enum GameEvent {
+    PieceSpawned(PieceSpawned),
+    PieceMoved(PieceMoved),
+    PieceRotated(PieceRotated),
+    PieceHeld(PieceHeld),
+    PieceLocked(PieceLocked),
+    LinesCleared(LinesCleared),
+    LevelUp(LevelUp),
+    GameOver(GameOver),
+}
+
+fn send<T: Send + Sync + 'static>(resources: &Resources, event: T) {
+    resources.get_mut::<Events<T>>().unwrap().send(event);
+}
+
+fn send_events_system(_world: &mut World, resources: &mut Resources) {
+    let events = std::mem::take(&mut resources.get_mut::<State>().unwrap().events);
+    for event in events {
+        match event {
+            GameEvent::PieceSpawned(event) => send(resources, event),
+            GameEvent::PieceMoved(event) => send(resources, event),
+            GameEvent::PieceRotated(event) => send(resources, event),
+            GameEvent::PieceHeld(event) => send(resources, event),
+            GameEvent::PieceLocked(event) => send(resources, event),
+            GameEvent::LinesCleared(event) => send(resources, event),
+            GameEvent::LevelUp(event) => send(resources, event),
+            GameEvent::GameOver(event) => send(resources, event),
+        }
+    }
+}
+
+pub struct GameEventsPlugin;
+
+impl Plugin for GameEventsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<PieceSpawned>()
+            .add_event::<PieceMoved>()
+            .add_event::<PieceRotated>()
+            .add_event::<PieceHeld>()
+            .add_event::<PieceLocked>()
+            .add_event::<LinesCleared>()
+            .add_event::<LevelUp>()
+            .add_event::<GameOver>()
+            .add_system_to_stage(stage::POST_UPDATE, send_events_system.thread_local_system());
+    }
+}
@@ -1,17 +1,237 @@
-use super::{get_color, LineTransition, PieceBag, PieceType, Pieces, Preview};
-use bevy::prelude::*;
+use super::{
+    clear_animation::ClearedCell,
+    config::Config,
+    dirty::DirtyCells,
+    layout::{HudPieces, Layout},
+    theme::Theme,
+    timestep::FixedTimestep,
+    Piece, PieceBag, PieceType, Pieces, Preview, State,
+};
+use bevy::{asset::LoadState, prelude::*};
 use rand::prelude::*;
 use std::collections::HashSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellKind {
+    Empty,
+    Garbage,
+    Piece(PieceType),
+}
+
+// A single cell of the playfield. The renderers map it to their colors / tiles, the game logic only cares
+// about the kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub kind: CellKind,
+    // variant of the tile within the kind, i.e. the parts of the I piece: 0 ..= 2 left end, middle and
+    // right end when horizontal, 3 ..= 5 top end, middle and bottom end when vertical, I_PLAIN without ends
+    pub tile: u8,
+    // drawn, but not part of the stack (e.g. a ghost piece)
+    pub ghost: bool,
+}
+
+impl Cell {
+    // tile of an I cell whose orientation is unknown
+    pub const I_PLAIN: u8 = 6;
+    pub const EMPTY: Cell = Cell {
+        kind: CellKind::Empty,
+        tile: 0,
+        ghost: false,
+    };
+    pub const GARBAGE: Cell = Cell {
+        kind: CellKind::Garbage,
+        tile: 0,
+        ghost: false,
+    };
+
+    pub fn piece(t: PieceType, tile: u8) -> Self {
+        Cell {
+            kind: CellKind::Piece(t),
+            tile,
+            ghost: false,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kind == CellKind::Empty
+    }
+
+    // blocks pieces from moving into it
+    pub fn is_solid(&self) -> bool {
+        !self.is_empty() && !self.ghost
+    }
+
+    // the hex digits of the piece and board notation (which are also the tile indices of the gb tile sets):
+    // 1 garbage, 2 a plain I cell, 3 ..= 8 L J S Z O T and 9 ..= e the parts of the I piece
+    pub fn from_code(c: u8) -> Option<Self> {
+        match c {
+            0 => Some(Cell::EMPTY),
+            1 => Some(Cell::GARBAGE),
+            2 => Some(Cell::piece(PieceType::I, Cell::I_PLAIN)),
+            3 => Some(Cell::piece(PieceType::L, 0)),
+            4 => Some(Cell::piece(PieceType::J, 0)),
+            5 => Some(Cell::piece(PieceType::S, 0)),
+            6 => Some(Cell::piece(PieceType::Z, 0)),
+            7 => Some(Cell::piece(PieceType::O, 0)),
+            8 => Some(Cell::piece(PieceType::T, 0)),
+            0x9..=0xe => Some(Cell::piece(PieceType::I, c - 0x9)),
+            _ => None,
+        }
+    }
+
+    pub fn code(&self) -> u8 {
+        match self.kind {
+            CellKind::Empty => 0,
+            CellKind::Garbage => 1,
+            CellKind::Piece(PieceType::L) => 3,
+            CellKind::Piece(PieceType::J) => 4,
+            CellKind::Piece(PieceType::S) => 5,
+            CellKind::Piece(PieceType::Z) => 6,
+            CellKind::Piece(PieceType::O) => 7,
+            CellKind::Piece(PieceType::T) => 8,
+            CellKind::Piece(PieceType::I) if self.tile < Cell::I_PLAIN => 0x9 + self.tile,
+            CellKind::Piece(PieceType::I) => 2,
+        }
+    }
+
+    // index into FieldMaterials
+    fn color(&self) -> usize {
+        match self.kind {
+            CellKind::Empty => 0,
+            CellKind::Garbage => 1,
+            CellKind::Piece(PieceType::I) => 2,
+            CellKind::Piece(PieceType::L) => 3,
+            CellKind::Piece(PieceType::J) => 4,
+            CellKind::Piece(PieceType::S) => 5,
+            CellKind::Piece(PieceType::Z) => 6,
+            CellKind::Piece(PieceType::O) => 7,
+            CellKind::Piece(PieceType::T) => 8,
+        }
+    }
+}
+
+pub type Board = [[Cell; 10]; 22];
+
+pub const EMPTY_BOARD: Board = [[Cell::EMPTY; 10]; 22];
+
+// all changes go through the setters, so that the renderers only need to touch the cells that changed
 pub struct Playfield {
-    pub field: [[u8; 10]; 22],
+    field: Board,
+    dirty: DirtyCells,
+    garbage_hole: usize,
 }
 
 impl Playfield {
     pub fn new() -> Self {
         Playfield {
-            field: [[0u8; 10]; 22],
+            field: EMPTY_BOARD,
+            dirty: DirtyCells::new(10, 22),
+            garbage_hole: 0,
+        }
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.field
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Cell {
+        self.field[y][x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, cell: Cell) {
+        if self.field[y][x] != cell {
+            self.field[y][x] = cell;
+            self.dirty.mark(x, y);
+        }
+    }
+
+    pub fn set_board(&mut self, board: Board) {
+        self.field = board;
+        self.dirty.mark_all();
+    }
+
+    // moves everything above the row down by one
+    pub fn remove_row(&mut self, y: usize) {
+        for y in y..self.field.len() - 1 {
+            self.field[y] = self.field[y + 1];
+            self.dirty.mark_row(y);
+        }
+        self.field[21] = [Cell::EMPTY; 10];
+        self.dirty.mark_row(21);
+    }
+
+    pub fn dirty(&self) -> &DirtyCells {
+        &self.dirty
+    }
+
+    // e.g. after the renderer or the tile set changed
+    pub fn mark_all_dirty(&mut self) {
+        self.dirty.mark_all();
+    }
+
+    // called by the active renderer once it is up to date
+    pub fn clear_dirty(&mut self) {
+        self.dirty.clear();
+    }
+
+    // pushes rows of garbage in from the bottom, each with a single hole. messiness is the chance
+    // of the hole moving to a new column from one row to the next (0.0: straight well, 1.0: random).
+    // returns false if blocks were pushed out of the top of the field.
+    pub fn push_garbage<R: Rng>(&mut self, rows: usize, messiness: f32, rng: &mut R) -> bool {
+        let rows = rows.min(self.field.len());
+        let overflow = self.field[self.field.len() - rows..]
+            .iter()
+            .any(|line| line.iter().any(|c| c.is_solid()));
+
+        for y in (rows..self.field.len()).rev() {
+            self.field[y] = self.field[y - rows];
+        }
+        for y in (0..rows).rev() {
+            if rng.gen::<f32>() < messiness {
+                // always pick a different column, otherwise messiness would be underestimated
+                self.garbage_hole = (self.garbage_hole + rng.gen_range(1, self.field[y].len()))
+                    % self.field[y].len();
+            }
+            self.field[y] = [Cell::GARBAGE; 10];
+            self.field[y][self.garbage_hole] = Cell::EMPTY;
         }
+        self.dirty.mark_all();
+        !overflow
     }
+
+    pub fn garbage_rows(&self) -> usize {
+        self.field
+            .iter()
+            .filter(|line| line.iter().any(|c| c.kind == CellKind::Garbage))
+            .count()
+    }
+}
+
+// parses a whole board in the same notation as the pieces: '.' is empty, hex digits are tiles and
+// 'o' / 'x' are garbage. The last line is the bottom row of the field.
+pub fn parse_board(i: &str) -> Result<Board, String> {
+    let lines = i.lines().collect::<Vec<_>>();
+    if lines.len() > 22 {
+        return Err(format!("board too high: {} lines", lines.len()));
+    }
+    let mut field = EMPTY_BOARD;
+    for (y, line) in lines.iter().rev().enumerate() {
+        let line = line.trim();
+        if line.chars().count() != 10 {
+            return Err(format!("board lines must be 10 wide: '{}'", line));
+        }
+        for (x, c) in line.chars().enumerate() {
+            field[y][x] = match c {
+                '.' => Cell::EMPTY,
+                'o' | 'x' => Cell::GARBAGE,
+                _ => c
+                    .to_digit(16)
+                    .and_then(|c| Cell::from_code(c as u8))
+                    .ok_or_else(|| format!("unexpected character in board: '{}'", c))?,
+            };
+        }
+    }
+    Ok(field)
 }
 
 pub struct Field {
@@ -20,12 +240,30 @@ pub struct Field {
     r: u32,
 }
 
+// the Field entity of each cell, for updating only the cells that changed. The textured renderer has no
+// entities for the two rows above the visible field
+#[derive(Default)]
+pub struct FieldEntities {
+    cells: [[Option<Entity>; 10]; 22],
+}
+
+impl FieldEntities {
+    fn get(&self, x: usize, y: usize) -> Option<Entity> {
+        self.cells[y][x]
+    }
+
+    fn clear(&mut self) {
+        self.cells = [[None; 10]; 22];
+    }
+}
+
 pub struct FieldMaterials {
     pub materials: Vec<Handle<ColorMaterial>>,
 }
 
-impl FieldMaterials {
-    fn new(mut materials: ResMut<Assets<ColorMaterial>>) -> Self {
+impl FromResources for FieldMaterials {
+    fn from_resources(resources: &Resources) -> Self {
+        let mut materials = resources.get_mut::<Assets<ColorMaterial>>().unwrap();
         let colors = [
             Color::rgb(0.0, 0.0, 0.0),
             Color::rgb(1.0, 1.0, 1.0),
@@ -49,8 +287,11 @@ impl FieldMaterials {
     // fn from_tilemap()
 }
 
-fn init_field_solid(mut commands: Commands, materials: ResMut<Assets<ColorMaterial>>) {
-    let field_materials = FieldMaterials::new(materials);
+fn spawn_field_solid(
+    commands: &mut Commands,
+    entities: &mut FieldEntities,
+    field_materials: &FieldMaterials,
+) {
     // tragicomic inversion: use sprites to emulate a primitive tiled background.
     // don't tell the TED chip in your c16, it might commit suicide...
     for y in 0..22 {
@@ -71,67 +312,113 @@ fn init_field_solid(mut commands: Commands, materials: ResMut<Assets<ColorMateri
                     y,
                     r: rand::thread_rng().gen::<u32>(),
                 });
+            entities.cells[y as usize][x as usize] = commands.current_entity();
         }
     }
-    commands.insert_resource(field_materials);
+}
+
+// the rows being cleared and the progress of the line transition
+fn clearing_rows(state: &State) -> (HashSet<usize>, f32) {
+    match &state.line_transition {
+        Some(lt) if !state.paused => (lt.to_eliminate.iter().cloned().collect(), lt.progress()),
+        _ => (HashSet::new(), 0.0),
+    }
+}
+
+// the board is hidden while the game is paused
+fn shown_cell(playfield: &Playfield, state: &State, x: usize, y: usize) -> Cell {
+    if state.paused {
+        Cell::EMPTY
+    } else {
+        playfield.get(x, y)
+    }
 }
 
 fn field_update_system_solid(
-    playfield: Res<Playfield>,
+    renderer: Res<Renderer>,
+    mut playfield: ResMut<Playfield>,
     materials: Res<FieldMaterials>,
-    mut query: Query<(&Field, &Sprite, &mut Handle<ColorMaterial>)>,
+    theme: Res<Theme>,
+    state: Res<State>,
+    entities: Res<FieldEntities>,
+    mut query: Query<(&Field, &mut Handle<ColorMaterial>)>,
 ) {
-    for (field, _, mut material) in &mut query.iter() {
-        *material =
-            materials.materials[playfield.field[field.y as usize][field.x as usize] as usize];
+    if *renderer != Renderer::Solid {
+        return;
+    }
+    let (eliminate_lines, progress) = clearing_rows(&state);
+    for (x, y) in playfield.dirty().changed(&eliminate_lines) {
+        let entity = match entities.get(x, y) {
+            Some(entity) => entity,
+            None => continue,
+        };
+        let color = if eliminate_lines.contains(&y) {
+            let r = match query.get::<Field>(entity) {
+                Ok(field) => field.r,
+                Err(_) => continue,
+            };
+            match theme
+                .clear_animation
+                .cell(&playfield.board()[y], x, progress, r)
+            {
+                ClearedCell::Cell(cell) => cell.color(),
+                ClearedCell::Empty => Cell::EMPTY.color(),
+                // white (as garbage) for the first half of the explosion
+                ClearedCell::Flash => Cell::GARBAGE.color(),
+                ClearedCell::Explode(p) if p < 0.5 => Cell::GARBAGE.color(),
+                ClearedCell::Explode(_) => Cell::EMPTY.color(),
+            }
+        } else {
+            shown_cell(&playfield, &state, x, y).color()
+        };
+        if let Ok(mut material) = query.get_mut::<Handle<ColorMaterial>>(entity) {
+            *material = materials.materials[color];
+        }
     }
+    playfield.clear_dirty();
 }
 
+// draws the hold box and the next queue at the positions of the layout
 fn preview_system_solid(
     mut commands: Commands,
+    renderer: Res<Renderer>,
+    state: Res<State>,
+    layout: Res<Layout>,
     mut piece_bag: ResMut<PieceBag>,
     field_materials: Res<FieldMaterials>,
     pieces: Res<Pieces>,
-    mut preview_query: Query<(Entity, &Preview, &PieceType)>,
+    mut shown: Local<Option<HudPieces>>,
+    mut preview_query: Query<(Entity, &Preview)>,
 ) {
-    let current_preview = piece_bag.peek_preview();
-    let mut create_preview = true;
-    for (ent, _, piece_type) in &mut preview_query.iter() {
-        if *piece_type != current_preview {
-            println!("despawn preview");
-            commands.despawn(ent);
-        } else {
-            create_preview = false; // this assumes that all Preview entities have the same PieceType
-        }
+    if *renderer != Renderer::Solid {
+        *shown = None;
+        return;
     }
-
-    let preview_pos = Vec3::new(32. * 12., 32. * 15., 0.);
-    if create_preview {
-        for (i, (x, y, _)) in pieces.get_solid_base(&current_preview)[0]
-            .iter()
-            .enumerate()
-        {
-            println!("spawn preview {}", i);
-            commands
-                .spawn(SpriteComponents {
-                    material: field_materials.materials[get_color(&current_preview)],
-                    transform: Transform::from_translation(
-                        Vec3::new((x * 32) as f32, (y * 32) as f32, 1.0) + preview_pos,
-                    ),
-                    sprite: Sprite::new(Vec2::new(32f32, 32f32)),
-                    ..Default::default()
-                })
-                .with(Preview)
-                .with(current_preview);
-        }
+    let hud = HudPieces::new(&state, piece_bag.peek_queue(layout.next.len()), &layout);
+    if shown.as_ref() == Some(&hud) {
+        return;
     }
+    for (ent, _) in &mut preview_query.iter() {
+        commands.despawn(ent);
+    }
+    for (pos, c) in hud.blocks(&pieces) {
+        commands
+            .spawn(SpriteComponents {
+                material: field_materials.materials[c.color()],
+                transform: Transform::from_translation(pos),
+                sprite: Sprite::new(Vec2::new(32f32, 32f32)),
+                ..Default::default()
+            })
+            .with(Preview);
+    }
+    *shown = Some(hud);
 }
 
 pub struct SolidFieldPlugin;
 
 impl Plugin for SolidFieldPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_startup_system(init_field_solid.system())
+        app.init_resource::<FieldMaterials>()
             // .add_system(scene_save_system.thread_local_system())
             .add_system(preview_system_solid.system())
             .add_system(field_update_system_solid.system());
@@ -140,20 +427,29 @@ impl Plugin for SolidFieldPlugin {
 
 // textured
 
-fn init_field_textured(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut textures: ResMut<Assets<Texture>>,
-    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
-) {
+// the tile set of the theme always lives in the default atlas handle, so switching themes only replaces the atlas
+fn load_theme_atlas(
+    asset_server: &AssetServer,
+    textures: &mut Assets<Texture>,
+    texture_atlases: &mut Assets<TextureAtlas>,
+    theme: &Theme,
+) -> Handle<TextureAtlas> {
     let texture_handle = asset_server
-        .load_sync(&mut textures, "assets/textures/gb.png")
+        .load_sync(textures, theme.texture)
         // .load_sync(&mut textures, "assets/textures/gabe-idle-run.png")
         .unwrap();
     let texture = textures.get(&texture_handle).unwrap();
-    let texture_atlas = TextureAtlas::from_grid(texture_handle, texture.size, 16, 2);
-    let texture_atlas_handle = texture_atlases.add_default(texture_atlas);
-    // texture_atlases.add_default(texture_atlas);
+    let (columns, rows) = theme.grid;
+    let texture_atlas = TextureAtlas::from_grid(texture_handle, texture.size, columns, rows);
+    texture_atlases.add_default(texture_atlas)
+}
+
+fn spawn_field_textured(
+    commands: &mut Commands,
+    entities: &mut FieldEntities,
+    texture_atlas_handle: Handle<TextureAtlas>,
+    scale: f32,
+) {
     // tragicomic inversion: use sprites to emulate a primitive tiled background.
     // don't tell the TED chip in your c16, it might commit suicide...
     for y in 0..20 {
@@ -161,7 +457,7 @@ fn init_field_textured(
             commands
                 .spawn(SpriteSheetComponents {
                     texture_atlas: texture_atlas_handle,
-                    transform: Transform::from_scale(4.0).with_translation(Vec3::new(
+                    transform: Transform::from_scale(scale).with_translation(Vec3::new(
                         (x * 32) as f32,
                         (y * 32) as f32,
                         1.0,
@@ -173,78 +469,118 @@ fn init_field_textured(
                     y,
                     r: rand::thread_rng().gen::<u32>(),
                 });
+            entities.cells[y as usize][x as usize] = commands.current_entity();
         }
     }
 }
 
-fn field_update_system_textured(
-    playfield: Res<Playfield>,
-    texture_atlases: Res<Assets<TextureAtlas>>,
-    mut query: Query<(&Field, &mut TextureAtlasSprite, &Handle<TextureAtlas>)>,
-    mut query_line_transitions: Query<&LineTransition>,
+// reloads the tile set after the theme was switched
+fn theme_texture_system(
+    asset_server: Res<AssetServer>,
+    theme: Res<Theme>,
+    mut playfield: ResMut<Playfield>,
+    mut loaded: Local<Option<&'static str>>,
+    mut textures: ResMut<Assets<Texture>>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
 ) {
-    let mut eliminate_lines = HashSet::new();
-    let mut progress = 0.0;
-    for lt in &mut query_line_transitions.iter() {
-        eliminate_lines = lt.to_eliminate.iter().cloned().collect();
-        progress = lt.timer.elapsed as f32 / lt.timer.duration as f32;
+    match *loaded {
+        // the initial theme is loaded by renderer_system
+        None => *loaded = Some(theme.name),
+        Some(name) if name != theme.name => {
+            load_theme_atlas(&asset_server, &mut textures, &mut texture_atlases, &theme);
+            // the tile indices differ between the tile sets
+            playfield.mark_all_dirty();
+            *loaded = Some(theme.name);
+        }
+        _ => (),
     }
+}
 
-    for (field, mut sprite, texture_atlas_handle) in &mut query.iter() {
-        let texture_atlas = texture_atlases.get(&texture_atlas_handle).unwrap();
-
-        let r = (field.r % 4) as i32;
-        // sprite.index = 16 + clamp(r + (progress * 12f32) as i32, 8) as u32;
-
-        let global_progress = (progress * 12f32) as i32;
-        let explode = eliminate_lines.contains(&(field.y as usize)) && global_progress >= r;
-
-        if explode {
-            sprite.index = 16 + (global_progress - r) as u32;
+fn field_update_system_textured(
+    renderer: Res<Renderer>,
+    mut playfield: ResMut<Playfield>,
+    theme: Res<Theme>,
+    state: Res<State>,
+    entities: Res<FieldEntities>,
+    mut query: Query<(&Field, &mut TextureAtlasSprite)>,
+) {
+    if *renderer != Renderer::Textured {
+        return;
+    }
+    let (eliminate_lines, progress) = clearing_rows(&state);
+    for (x, y) in playfield.dirty().changed(&eliminate_lines) {
+        let entity = match entities.get(x, y) {
+            Some(entity) => entity,
+            None => continue,
+        };
+        let index = if eliminate_lines.contains(&y) {
+            let r = match query.get::<Field>(entity) {
+                Ok(field) => field.r,
+                Err(_) => continue,
+            };
+            let cleared = theme
+                .clear_animation
+                .cell(&playfield.board()[y], x, progress, r);
+            match (cleared, theme.explosion) {
+                (ClearedCell::Cell(cell), _) => theme.tile(cell),
+                (ClearedCell::Explode(p), Some((first_frame, frames))) => {
+                    first_frame + ((p * frames as f32) as u32).min(frames - 1)
+                }
+                // without explosion frames the cells just disappear, flashing blinks with the empty tile
+                (ClearedCell::Explode(_), None)
+                | (ClearedCell::Empty, _)
+                | (ClearedCell::Flash, _) => theme.tile(Cell::EMPTY),
+            }
         } else {
-            sprite.index = (playfield.field[field.y as usize][field.x as usize] as usize
-                % texture_atlas.textures.len()) as u32;
+            theme.tile(shown_cell(&playfield, &state, x, y))
+        };
+        if let Ok(mut sprite) = query.get_mut::<TextureAtlasSprite>(entity) {
+            sprite.index = index;
         }
     }
+    playfield.clear_dirty();
 }
 
 fn preview_system_textured(
     mut commands: Commands,
+    renderer: Res<Renderer>,
+    state: Res<State>,
+    layout: Res<Layout>,
     mut piece_bag: ResMut<PieceBag>,
     pieces: Res<Pieces>,
-    mut preview_query: Query<(Entity, &Preview, &PieceType)>,
+    theme: Res<Theme>,
+    mut shown: Local<Option<(HudPieces, &'static str)>>,
+    mut preview_query: Query<(Entity, &Preview)>,
 ) {
-    let current_preview = piece_bag.peek_preview();
-    let mut create_preview = true;
-    for (ent, _, piece_type) in &mut preview_query.iter() {
-        if *piece_type != current_preview {
-            // println!("despawn preview");
-            commands.despawn(ent);
-        } else {
-            create_preview = false; // this assumes that all Preview entities have the same PieceType
-        }
+    if *renderer != Renderer::Textured {
+        *shown = None;
+        return;
     }
-    // let texture_atlas = texture_atlases.get(&Handle::default()).unwrap();
-    let preview_pos = Vec3::new(32. * 12., 32. * 15., 0.);
-    if create_preview {
-        for (x, y, c) in pieces.get_solid_base(&current_preview)[0].iter() {
-            // println!("spawn preview {}", i);
-            commands
-                .spawn(SpriteSheetComponents {
-                    texture_atlas: Handle::default(),
-                    transform: Transform::from_scale(4.0).with_translation(
-                        Vec3::new((x * 32) as f32, (y * 32) as f32, 1.0) + preview_pos,
-                    ),
-                    sprite: TextureAtlasSprite {
-                        index: *c as u32,
-                        ..Default::default()
-                    },
+    // the tiles change with the theme
+    let hud = (
+        HudPieces::new(&state, piece_bag.peek_queue(layout.next.len()), &layout),
+        theme.name,
+    );
+    if shown.as_ref() == Some(&hud) {
+        return;
+    }
+    for (ent, _) in &mut preview_query.iter() {
+        commands.despawn(ent);
+    }
+    for (pos, c) in hud.0.blocks(&pieces) {
+        commands
+            .spawn(SpriteSheetComponents {
+                texture_atlas: Handle::default(),
+                transform: Transform::from_scale(theme.tile_scale()).with_translation(pos),
+                sprite: TextureAtlasSprite {
+                    index: theme.tile(c),
                     ..Default::default()
-                })
-                .with(Preview)
-                .with(current_preview);
-        }
+                },
+                ..Default::default()
+            })
+            .with(Preview);
     }
+    *shown = Some(hud);
 }
 
 pub struct TexturedFieldPlugin;
@@ -263,7 +599,7 @@ pub struct TexturedFieldPlugin;
 impl Plugin for TexturedFieldPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app
-        .add_startup_system(init_field_textured.system())
+        .add_system(theme_texture_system.system())
         .add_system(field_update_system_textured.system())
         .add_system(preview_system_textured.system())
         // .add_system(scene_save_system.thread_local_system())
@@ -272,3 +608,286 @@ impl Plugin for TexturedFieldPlugin {
        ;
     }
 }
+
+// renderer selection
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Renderer {
+    // flat colored blocks, for low-end machines
+    Solid,
+    Textured,
+}
+
+impl Renderer {
+    // `bevris --renderer solid`, or `renderer solid` in the config file
+    fn from_args(config: &Config) -> Self {
+        let args = std::env::args().collect::<Vec<_>>();
+        let name = args
+            .iter()
+            .position(|arg| arg == "--renderer")
+            .and_then(|i| args.get(i + 1))
+            .map(|name| name.as_str())
+            .or_else(|| config.get("renderer"));
+        match name {
+            None | Some("textured") => Renderer::Textured,
+            Some("solid") => Renderer::Solid,
+            Some(name) => {
+                println!("unknown renderer: {}. using textured", name);
+                Renderer::Textured
+            }
+        }
+    }
+}
+
+// F3 toggles the renderer
+fn renderer_toggle_system(keyboard_input: Res<Input<KeyCode>>, mut renderer: ResMut<Renderer>) {
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        *renderer = match *renderer {
+            Renderer::Solid => Renderer::Textured,
+            Renderer::Textured => Renderer::Solid,
+        };
+    }
+}
+
+// tears down the field and preview entities of the previous renderer and spawns the ones of the current
+fn renderer_system(
+    mut commands: Commands,
+    renderer: Res<Renderer>,
+    mut playfield: ResMut<Playfield>,
+    mut spawned: Local<Option<Renderer>>,
+    asset_server: Res<AssetServer>,
+    theme: Res<Theme>,
+    field_materials: Res<FieldMaterials>,
+    mut entities: ResMut<FieldEntities>,
+    mut textures: ResMut<Assets<Texture>>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    mut field_query: Query<(Entity, &Field)>,
+    mut preview_query: Query<(Entity, &Preview)>,
+) {
+    if *spawned == Some(*renderer) {
+        return;
+    }
+    for (entity, _) in &mut field_query.iter() {
+        commands.despawn(entity);
+    }
+    for (entity, _) in &mut preview_query.iter() {
+        commands.despawn(entity);
+    }
+    entities.clear();
+    match *renderer {
+        Renderer::Solid => spawn_field_solid(&mut commands, &mut entities, &field_materials),
+        Renderer::Textured => {
+            let texture_atlas_handle =
+                load_theme_atlas(&asset_server, &mut textures, &mut texture_atlases, &theme);
+            spawn_field_textured(
+                &mut commands,
+                &mut entities,
+                texture_atlas_handle,
+                theme.tile_scale(),
+            );
+        }
+    }
+    playfield.mark_all_dirty();
+    *spawned = Some(*renderer);
+}
+
+// The ui camera crashes wgpu when the ui pass is set up before the tile atlas of the sprite sheets is in place, so
+// it is only spawned once the atlas texture is loaded. The atlas is loaded for the solid renderer as well, to keep
+// switching renderers safe.
+fn ui_camera_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    theme: Res<Theme>,
+    mut spawned: Local<bool>,
+    mut textures: ResMut<Assets<Texture>>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+) {
+    if *spawned {
+        return;
+    }
+    let texture = match texture_atlases.get(&Handle::default()) {
+        Some(atlas) => atlas.texture,
+        None => {
+            load_theme_atlas(&asset_server, &mut textures, &mut texture_atlases, &theme);
+            return;
+        }
+    };
+    let loaded = match asset_server.get_load_state(texture) {
+        Some(LoadState::Loaded(_)) => true,
+        // load_sync doesn't track a load state, the texture is in place once it is in the assets
+        None => textures.get(&texture).is_some(),
+        Some(_) => false,
+    };
+    if loaded {
+        commands.spawn(UiCameraComponents::default());
+        *spawned = true;
+    }
+}
+
+// collapse
+
+// in simulation ticks, i.e. 0.2 seconds
+const COLLAPSE_TICKS: u32 = 12;
+
+// Lets the rows above cleared lines fall into place. The playfield already is in its final state, only the
+// field sprites are moved.
+pub struct Collapse {
+    enabled: bool,
+    // rows of the running line transition
+    clearing: Vec<usize>,
+    // number of rows each (final) row falls
+    offsets: [usize; 22],
+    // ticks since the start, COLLAPSE_TICKS when idle
+    ticks: u32,
+}
+
+impl Collapse {
+    // `bevris --collapse`, or `collapse on` in the config file
+    fn from_args(config: &Config) -> Self {
+        Collapse {
+            enabled: std::env::args().any(|arg| arg == "--collapse")
+                || config.get("collapse") == Some("on"),
+            clearing: Vec::new(),
+            offsets: [0; 22],
+            ticks: COLLAPSE_TICKS,
+        }
+    }
+
+    fn start(&mut self, cleared: &[usize]) {
+        // the y-th remaining row ends up in row y, the new empty rows come from above the field
+        let mut from = (0..22).filter(|y| !cleared.contains(y)).chain(22..);
+        for (y, offset) in self.offsets.iter_mut().enumerate() {
+            *offset = from.next().unwrap() - y;
+        }
+        self.ticks = 0;
+    }
+}
+
+fn collapse_system(
+    timestep: Res<FixedTimestep>,
+    state: Res<State>,
+    pieces: Res<Pieces>,
+    mut collapse: ResMut<Collapse>,
+    mut piece_query: Query<(&PieceType, &Piece)>,
+    mut query: Query<(&Field, &mut Transform)>,
+) {
+    match &state.line_transition {
+        Some(lt) => {
+            collapse.clearing = lt.to_eliminate.clone();
+            return;
+        }
+        // the line transition just ended, the rows have been removed from the playfield
+        None if !collapse.clearing.is_empty() => {
+            let cleared = std::mem::take(&mut collapse.clearing);
+            if collapse.enabled {
+                collapse.start(&cleared);
+            }
+        }
+        None => (),
+    }
+    // stands still while paused, like the simulation
+    if collapse.ticks >= COLLAPSE_TICKS || state.paused {
+        return;
+    }
+
+    collapse.ticks = (collapse.ticks + timestep.ticks).min(COLLAPSE_TICKS);
+    let progress = collapse.ticks as f32 / COLLAPSE_TICKS as f32;
+    // accelerating, like falling
+    let remaining = 1.0 - progress * progress;
+    // the active piece is already placed at its final position
+    let mut active = Vec::new();
+    for (t, p) in &mut piece_query.iter() {
+        if state.piece_on_board() {
+            active.extend(pieces.get_solid(&t, &p).iter().map(|(x, y, _)| (*x, *y)));
+        }
+    }
+    for (field, mut transform) in &mut query.iter() {
+        let offset = if active.contains(&(field.x, field.y)) {
+            0.0
+        } else {
+            collapse.offsets[field.y as usize] as f32 * remaining
+        };
+        transform.set_translation(Vec3::new(
+            (field.x * 32) as f32,
+            (field.y as f32 + offset) * 32.0,
+            1.0,
+        ));
+    }
+}
+
+pub struct FieldPlugin;
+
+impl Plugin for FieldPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let config = Config::load();
+        app.add_resource(Renderer::from_args(&config))
+            .init_resource::<FieldEntities>()
+            .add_resource(Collapse::from_args(&config))
+            .add_system(collapse_system.system())
+            .add_plugin(SolidFieldPlugin)
+            .add_plugin(TexturedFieldPlugin)
+            .add_system(renderer_toggle_system.system())
+            .add_system(ui_camera_system.system())
+            // before the update systems see the new entities (commands are applied at the end of the stage)
+            .add_system_to_stage(stage::PRE_UPDATE, renderer_system.system());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cell_codes() {
+        for code in 0..16 {
+            match Cell::from_code(code) {
+                Some(cell) => {
+                    assert_eq!(cell.code(), code);
+                    assert_eq!(Cell::from_code(cell.code()), Some(cell));
+                }
+                None => assert!(code > 0xe),
+            }
+        }
+    }
+
+    fn hole(line: &[Cell; 10]) -> usize {
+        assert_eq!(line.iter().filter(|c| c.is_empty()).count(), 1);
+        line.iter().position(|c| c.is_empty()).unwrap()
+    }
+
+    #[test]
+    fn garbage_straight_well() {
+        let mut playfield = Playfield::new();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        assert!(playfield.push_garbage(10, 0.0, &mut rng));
+        assert_eq!(playfield.garbage_rows(), 10);
+        let column = hole(&playfield.board()[0]);
+        for line in playfield.board()[..10].iter() {
+            assert_eq!(hole(line), column);
+        }
+        assert!(playfield.board()[10].iter().all(|c| c.is_empty()));
+    }
+
+    #[test]
+    fn garbage_messy() {
+        let mut playfield = Playfield::new();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(2);
+        assert!(playfield.push_garbage(16, 1.0, &mut rng));
+        let holes = playfield.board()[..16].iter().map(hole).collect::<Vec<_>>();
+        assert!(holes.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn garbage_pushes_the_stack_up() {
+        let mut playfield = Playfield::new();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        playfield.set(4, 0, Cell::piece(PieceType::T, 0));
+        assert!(playfield.push_garbage(2, 0.5, &mut rng));
+        assert_eq!(playfield.get(4, 2), Cell::piece(PieceType::T, 0));
+        assert_eq!(playfield.garbage_rows(), 2);
+
+        // blocks in the top rows are pushed out
+        playfield.set(0, 21, Cell::GARBAGE);
+        assert!(!playfield.push_garbage(1, 0.5, &mut rng));
+    }
+}
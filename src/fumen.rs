@@ -0,0 +1,709 @@
+use super::{
+    field::{Board, Cell, CellKind, Playfield, EMPTY_BOARD},
+    Piece, PieceType, Pieces, State,
+};
+use bevy::prelude::*;
+
+// Encoding and decoding of fumen (v115) strings, the format commonly used for sharing boards. A fumen
+// consists of pages, each with a board (stored as a diff against the previous page) and an optional piece.
+//
+// The fumen field is 23 rows high plus a garbage row below the floor, stored top to bottom. Pieces are
+// described by type, rotation and the position of their (SRS) rotation center.
+
+const TABLE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const PREFIX: &str = "v115@";
+const WIDTH: usize = 10;
+const HEIGHT: usize = 24;
+const BLOCKS: usize = WIDTH * HEIGHT;
+const EXPORT_FILE: &str = "bevris_fumen.txt";
+
+// fumen cell / piece types
+const EMPTY: u8 = 0;
+const GRAY: u8 = 8;
+
+#[derive(Clone)]
+pub struct Page {
+    // the board without the piece
+    pub field: Board,
+    pub piece: Option<(PieceType, Piece)>,
+    // the piece is part of the board on the next page (and full lines are cleared)
+    pub lock: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Action {
+    kind: u8,
+    rotation: u8,
+    // rotation center, y counted from the bottom
+    x: i32,
+    y: i32,
+    raise: bool,
+    mirror: bool,
+    lock: bool,
+}
+
+struct RawPage {
+    field: [u8; BLOCKS],
+    action: Action,
+}
+
+fn kind_from_piece_type(t: PieceType) -> u8 {
+    match t {
+        PieceType::I => 1,
+        PieceType::L => 2,
+        PieceType::O => 3,
+        PieceType::Z => 4,
+        PieceType::T => 5,
+        PieceType::J => 6,
+        PieceType::S => 7,
+    }
+}
+
+fn piece_type_from_kind(kind: u8) -> Option<PieceType> {
+    match kind {
+        1 => Some(PieceType::I),
+        2 => Some(PieceType::L),
+        3 => Some(PieceType::O),
+        4 => Some(PieceType::Z),
+        5 => Some(PieceType::T),
+        6 => Some(PieceType::J),
+        7 => Some(PieceType::S),
+        _ => None,
+    }
+}
+
+fn kind_from_cell(c: &Cell) -> u8 {
+    match c.kind {
+        CellKind::Empty => EMPTY,
+        CellKind::Garbage => GRAY,
+        CellKind::Piece(t) => kind_from_piece_type(t),
+    }
+}
+
+fn cell_from_kind(kind: u8) -> Cell {
+    match piece_type_from_kind(kind) {
+        // the orientation of the I end caps is lost, use the horizontal middle part
+        Some(PieceType::I) => Cell::piece(PieceType::I, 1),
+        Some(t) => Cell::piece(t, 0),
+        None if kind == EMPTY => Cell::EMPTY,
+        None => Cell::GARBAGE,
+    }
+}
+
+// fumen rotations: 0 reverse, 1 right, 2 spawn, 3 left
+fn blocks(kind: u8, rotation: u8) -> [(i32, i32); 4] {
+    let mut blocks = match kind {
+        1 => [(0, 0), (-1, 0), (1, 0), (2, 0)],
+        2 => [(0, 0), (-1, 0), (1, 0), (1, 1)],
+        3 => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        4 => [(0, 0), (1, 0), (0, 1), (-1, 1)],
+        5 => [(0, 0), (-1, 0), (1, 0), (0, 1)],
+        6 => [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+        _ => [(0, 0), (-1, 0), (0, 1), (1, 1)],
+    };
+    for (x, y) in blocks.iter_mut() {
+        let (bx, by) = (*x, *y);
+        let (rx, ry) = match rotation {
+            0 => (-bx, -by),
+            1 => (by, -bx),
+            2 => (bx, by),
+            _ => (-by, bx),
+        };
+        *x = rx;
+        *y = ry;
+    }
+    blocks
+}
+
+// fumen stores some rotations of O, I, S and Z relative to a different origin than their rotation center
+fn legacy_offset(kind: u8, rotation: u8) -> (i32, i32) {
+    match (kind, rotation) {
+        (3, 3) => (1, -1),
+        (3, 0) => (1, 0),
+        (3, 2) => (0, -1),
+        (1, 0) => (1, 0),
+        (1, 3) => (0, -1),
+        (7, 2) => (0, -1),
+        (4, 1) => (-1, 0),
+        _ => (0, 0),
+    }
+}
+
+fn sorted_cells(mut cells: [(i32, i32); 4]) -> [(i32, i32); 4] {
+    cells.sort_by_key(|(x, y)| (*y, *x));
+    cells
+}
+
+fn action_from_piece(pieces: &Pieces, t: PieceType, p: &Piece, lock: bool) -> Action {
+    let solid = pieces.get_solid(&t, p);
+    let cells = sorted_cells([
+        (solid[0].0, solid[0].1),
+        (solid[1].0, solid[1].1),
+        (solid[2].0, solid[2].1),
+        (solid[3].0, solid[3].1),
+    ]);
+    let kind = kind_from_piece_type(t);
+    // find the fumen rotation with the same shape, the offset between the two is the rotation center
+    let (rotation, x, y) = (0..4)
+        .filter_map(|rotation| {
+            let shape = sorted_cells(blocks(kind, rotation));
+            let (dx, dy) = (cells[0].0 - shape[0].0, cells[0].1 - shape[0].1);
+            if shape
+                .iter()
+                .zip(cells.iter())
+                .all(|(s, c)| (s.0 + dx, s.1 + dy) == *c)
+            {
+                Some((rotation, dx, dy))
+            } else {
+                None
+            }
+        })
+        .next()
+        .expect("piece shape does not match any fumen rotation");
+    Action {
+        kind,
+        rotation,
+        x,
+        y,
+        raise: false,
+        mirror: false,
+        lock,
+    }
+}
+
+fn piece_from_action(pieces: &Pieces, action: &Action) -> Option<(PieceType, Piece)> {
+    let t = piece_type_from_kind(action.kind)?;
+    let cells = sorted_cells(blocks(action.kind, action.rotation));
+    pieces
+        .get_solid_base(&t)
+        .iter()
+        .enumerate()
+        .find_map(|(rot, base)| {
+            let shape = sorted_cells([
+                (base[0].0, base[0].1),
+                (base[1].0, base[1].1),
+                (base[2].0, base[2].1),
+                (base[3].0, base[3].1),
+            ]);
+            let (dx, dy) = (cells[0].0 - shape[0].0, cells[0].1 - shape[0].1);
+            if shape
+                .iter()
+                .zip(cells.iter())
+                .all(|(s, c)| (s.0 + dx, s.1 + dy) == *c)
+            {
+                Some((
+                    t,
+                    Piece {
+                        x: action.x + dx,
+                        y: action.y + dy,
+                        rot: rot as i32,
+                    },
+                ))
+            } else {
+                None
+            }
+        })
+}
+
+fn to_raw_field(field: &Board) -> [u8; BLOCKS] {
+    let mut raw = [EMPTY; BLOCKS];
+    for (y, line) in field.iter().enumerate() {
+        for (x, c) in line.iter().enumerate() {
+            raw[(HEIGHT - 2 - y) * WIDTH + x] = kind_from_cell(c);
+        }
+    }
+    raw
+}
+
+// the top row and the garbage row do not exist in the playfield and are dropped
+fn from_raw_field(raw: &[u8; BLOCKS]) -> Board {
+    let mut field = EMPTY_BOARD;
+    for (y, line) in field.iter_mut().enumerate() {
+        for (x, c) in line.iter_mut().enumerate() {
+            *c = cell_from_kind(raw[(HEIGHT - 2 - y) * WIDTH + x]);
+        }
+    }
+    field
+}
+
+// the board of the next page, i.e. with the piece locked, lines cleared and garbage risen
+fn next_raw_field(field: &[u8; BLOCKS], action: &Action) -> [u8; BLOCKS] {
+    let mut field = *field;
+    if !action.lock {
+        return field;
+    }
+    if action.kind != EMPTY {
+        for (bx, by) in blocks(action.kind, action.rotation).iter() {
+            let (x, y) = (action.x + bx, action.y + by);
+            if x >= 0 && x < WIDTH as i32 && y >= 0 && y < HEIGHT as i32 - 1 {
+                field[(HEIGHT - 2 - y as usize) * WIDTH + x as usize] = action.kind;
+            }
+        }
+    }
+
+    // the garbage row is never cleared
+    let mut lines = field[..BLOCKS - WIDTH]
+        .chunks(WIDTH)
+        .filter(|line| line.contains(&EMPTY))
+        .map(|line| line.to_vec())
+        .collect::<Vec<_>>();
+    while lines.len() < HEIGHT - 1 {
+        lines.insert(0, vec![EMPTY; WIDTH]);
+    }
+    if action.raise {
+        lines.remove(0);
+        lines.push(field[BLOCKS - WIDTH..].to_vec());
+        lines.push(vec![EMPTY; WIDTH]);
+    } else {
+        lines.push(field[BLOCKS - WIDTH..].to_vec());
+    }
+    if action.mirror {
+        for line in lines.iter_mut().take(HEIGHT - 1) {
+            line.reverse();
+        }
+    }
+
+    for (i, c) in lines.concat().iter().enumerate() {
+        field[i] = *c;
+    }
+    field
+}
+
+fn push_value(out: &mut Vec<u8>, mut value: usize, digits: usize) {
+    for _ in 0..digits {
+        out.push((value % 64) as u8);
+        value /= 64;
+    }
+}
+
+fn encode_raw(pages: &[RawPage]) -> String {
+    let mut values = Vec::new();
+    let mut prev = [EMPTY; BLOCKS];
+    // position of the repeat counter of the last unchanged field
+    let mut repeat = None;
+
+    for (i, page) in pages.iter().enumerate() {
+        let diffs = page
+            .field
+            .iter()
+            .zip(prev.iter())
+            .map(|(c, p)| *c as usize + 8 - *p as usize)
+            .collect::<Vec<_>>();
+
+        if diffs.iter().all(|diff| *diff == 8) {
+            match repeat {
+                Some(pos) if values[pos] < 63 => values[pos] += 1,
+                _ => {
+                    push_value(&mut values, 8 * BLOCKS + BLOCKS - 1, 2);
+                    repeat = Some(values.len());
+                    values.push(0);
+                }
+            }
+        } else {
+            repeat = None;
+            let mut start = 0;
+            while start < BLOCKS {
+                let count = diffs[start..]
+                    .iter()
+                    .take_while(|diff| **diff == diffs[start])
+                    .count();
+                push_value(&mut values, diffs[start] * BLOCKS + count - 1, 2);
+                start += count;
+            }
+        }
+
+        let action = &page.action;
+        let (ox, oy) = legacy_offset(action.kind, action.rotation);
+        let (x, y) = if action.kind == EMPTY {
+            (0, HEIGHT as i32 - 2)
+        } else {
+            (action.x - ox, action.y - oy)
+        };
+        let coordinate = (HEIGHT as i32 - 2 - y) as usize * WIDTH + x as usize;
+        let mut flags = action.raise as usize;
+        flags |= (action.mirror as usize) << 1;
+        // colorize (guideline colors) is only set on the first page
+        flags |= ((i == 0) as usize) << 2;
+        // bit 3 would be a comment
+        flags |= (!action.lock as usize) << 4;
+        let value = action.kind as usize
+            + 8 * (action.rotation as usize + 4 * (coordinate + BLOCKS * flags));
+        push_value(&mut values, value, 3);
+
+        prev = next_raw_field(&page.field, action);
+    }
+
+    let data = values
+        .iter()
+        .map(|v| TABLE[*v as usize] as char)
+        .collect::<String>();
+    // a '?' is inserted every 47 characters (including the prefix)
+    let mut out = PREFIX.to_string();
+    out.push_str(&data[..data.len().min(42)]);
+    if data.len() > 42 {
+        for chunk in data.as_bytes()[42..].chunks(47) {
+            out.push('?');
+            out.push_str(std::str::from_utf8(chunk).unwrap());
+        }
+    }
+    out
+}
+
+struct Reader {
+    values: Vec<usize>,
+    pos: usize,
+}
+
+impl Reader {
+    fn is_empty(&self) -> bool {
+        self.pos >= self.values.len()
+    }
+
+    // values are stored little endian
+    fn poll(&mut self, digits: usize) -> Result<usize, String> {
+        if self.pos + digits > self.values.len() {
+            return Err("unexpected end of fumen".to_string());
+        }
+        let value = self.values[self.pos..self.pos + digits]
+            .iter()
+            .rev()
+            .fold(0, |acc, v| acc * 64 + v);
+        self.pos += digits;
+        Ok(value)
+    }
+}
+
+fn decode_raw(data: &str) -> Result<(Vec<RawPage>, [u8; BLOCKS]), String> {
+    let data = data.trim();
+    if !data.starts_with(PREFIX) {
+        return Err(format!("unsupported fumen version (expected {})", PREFIX));
+    }
+    let values = data[PREFIX.len()..]
+        .chars()
+        .filter(|c| *c != '?')
+        .map(|c| {
+            TABLE
+                .iter()
+                .position(|t| *t as char == c)
+                .ok_or_else(|| format!("invalid character in fumen: {}", c))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut reader = Reader { values, pos: 0 };
+    let mut pages = Vec::new();
+    let mut prev = [EMPTY; BLOCKS];
+    let mut repeat = 0;
+    while !reader.is_empty() {
+        let mut field = prev;
+        if repeat > 0 {
+            repeat -= 1;
+        } else {
+            let mut i = 0;
+            while i < BLOCKS {
+                let value = reader.poll(2)?;
+                let (diff, count) = (value / BLOCKS, value % BLOCKS + 1);
+                if diff == 8 && count == BLOCKS {
+                    repeat = reader.poll(1)?;
+                }
+                if i + count > BLOCKS || diff > 16 {
+                    return Err("invalid field in fumen".to_string());
+                }
+                for c in field[i..i + count].iter_mut() {
+                    *c = (*c as usize + diff)
+                        .checked_sub(8)
+                        .filter(|c| *c <= GRAY as usize)
+                        .ok_or("invalid field in fumen")? as u8;
+                }
+                i += count;
+            }
+        }
+
+        let mut value = reader.poll(3)?;
+        let kind = (value % 8) as u8;
+        value /= 8;
+        let rotation = (value % 4) as u8;
+        value /= 4;
+        let coordinate = value % BLOCKS;
+        value /= BLOCKS;
+        let raise = value % 2 == 1;
+        value /= 2;
+        let mirror = value % 2 == 1;
+        value /= 2;
+        let _colorize = value % 2 == 1;
+        value /= 2;
+        let comment = value % 2 == 1;
+        value /= 2;
+        let lock = value % 2 == 0;
+
+        if comment {
+            // comments are not supported, skip them (4 characters are packed into 5 values)
+            let len = reader.poll(2)?;
+            for _ in 0..(len + 3) / 4 {
+                reader.poll(5)?;
+            }
+        }
+
+        let (ox, oy) = legacy_offset(kind, rotation);
+        let action = Action {
+            kind,
+            rotation,
+            x: (coordinate % WIDTH) as i32 + ox,
+            y: (HEIGHT - 2 - coordinate / WIDTH) as i32 + oy,
+            raise,
+            mirror,
+            lock,
+        };
+        prev = next_raw_field(&field, &action);
+        pages.push(RawPage { field, action });
+    }
+    Ok((pages, prev))
+}
+
+pub fn encode(pages: &[Page], pieces: &Pieces) -> String {
+    let raw_pages = pages
+        .iter()
+        .map(|page| RawPage {
+            field: to_raw_field(&page.field),
+            action: match page.piece {
+                Some((t, ref p)) => action_from_piece(pieces, t, p, page.lock),
+                None => Action {
+                    kind: EMPTY,
+                    rotation: 0,
+                    x: 0,
+                    y: 0,
+                    raise: false,
+                    mirror: false,
+                    lock: page.lock,
+                },
+            },
+        })
+        .collect::<Vec<_>>();
+    encode_raw(&raw_pages)
+}
+
+pub fn decode(data: &str, pieces: &Pieces) -> Result<Vec<Page>, String> {
+    let (raw_pages, _) = decode_raw(data)?;
+    Ok(raw_pages
+        .iter()
+        .map(|page| Page {
+            field: from_raw_field(&page.field),
+            piece: piece_from_action(pieces, &page.action),
+            lock: page.action.lock,
+        })
+        .collect())
+}
+
+// the board after the last page, e.g. to load an opening
+pub fn decode_board(data: &str) -> Result<Board, String> {
+    let (_, field) = decode_raw(data)?;
+    Ok(from_raw_field(&field))
+}
+
+// the current board with the active piece as a single page
+fn current_page(
+    playfield: &Playfield,
+    state: &State,
+    pieces: &Pieces,
+    t: PieceType,
+    p: &Piece,
+) -> Page {
+    let mut field = *playfield.board();
+    if state.piece_on_board() {
+        for (x, y, _) in pieces.get_solid(&t, p).iter() {
+            field[*y as usize][*x as usize] = Cell::EMPTY;
+        }
+    }
+    Page {
+        field,
+        piece: Some((t, p.clone())),
+        lock: true,
+    }
+}
+
+// `bevris --fumen <data>` starts with the board of a fumen (after its last page)
+fn setup_fumen_board(mut playfield: ResMut<Playfield>) {
+    let args = std::env::args().collect::<Vec<_>>();
+    let data = match args
+        .iter()
+        .position(|arg| arg == "--fumen")
+        .and_then(|i| args.get(i + 1))
+    {
+        Some(data) => data,
+        None => return,
+    };
+    match decode_board(data) {
+        Ok(field) => playfield.set_board(field),
+        Err(err) => println!("failed to load fumen: {}", err),
+    }
+}
+
+// F5 exports the current board, F6 the whole game so far
+fn fumen_export_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    state: Res<State>,
+    playfield: Res<Playfield>,
+    pieces: Res<Pieces>,
+    mut query: Query<(&PieceType, &Piece)>,
+) {
+    let mut pages = if keyboard_input.just_pressed(KeyCode::F6) {
+        state.placements.clone()
+    } else if keyboard_input.just_pressed(KeyCode::F5) {
+        Vec::new()
+    } else {
+        return;
+    };
+    for (t, p) in &mut query.iter() {
+        pages.push(current_page(&playfield, &state, &pieces, *t, &p));
+    }
+
+    let fumen = encode(&pages, &pieces);
+    println!("{}", fumen);
+    if let Err(err) = std::fs::write(EXPORT_FILE, format!("{}\n", fumen)) {
+        println!("failed to write {}: {}", EXPORT_FILE, err);
+    }
+}
+
+pub struct FumenPlugin;
+
+impl Plugin for FumenPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_startup_system(setup_fumen_board.system())
+            .add_system(fumen_export_system.system());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::parse_board;
+
+    fn cells(pieces: &Pieces, piece: &Option<(PieceType, Piece)>) -> Option<Vec<(i32, i32)>> {
+        piece.as_ref().map(|(t, p)| {
+            let mut cells = pieces
+                .get_solid(t, p)
+                .iter()
+                .map(|(x, y, _)| (*x, *y))
+                .collect::<Vec<_>>();
+            cells.sort();
+            cells
+        })
+    }
+
+    #[test]
+    fn round_trip() {
+        let pieces = Pieces::default();
+        // only the cells fumen can represent, i.e. without the orientation of the tiles
+        let mut field = parse_board("x.........\nxxxxxxx..x\nxxxxxxx.xx").unwrap();
+        field[2][1] = cell_from_kind(kind_from_piece_type(PieceType::I));
+        field[2][2] = cell_from_kind(kind_from_piece_type(PieceType::S));
+        let pages = vec![
+            Page {
+                field,
+                piece: Some((PieceType::T, Piece { x: 6, y: 0, rot: 2 })),
+                lock: true,
+            },
+            Page {
+                field: EMPTY_BOARD,
+                piece: Some((PieceType::L, Piece { x: 2, y: 5, rot: 1 })),
+                lock: false,
+            },
+            Page {
+                field: EMPTY_BOARD,
+                piece: None,
+                lock: true,
+            },
+        ];
+        let decoded = decode(&encode(&pages, &pieces), &pieces).unwrap();
+        assert_eq!(decoded.len(), pages.len());
+        for (decoded, page) in decoded.iter().zip(pages.iter()) {
+            assert!(decoded.field == page.field);
+            assert_eq!(cells(&pieces, &decoded.piece), cells(&pieces, &page.piece));
+            assert_eq!(decoded.lock, page.lock);
+        }
+    }
+
+    #[test]
+    fn decode_empty() {
+        let pieces = Pieces::default();
+        let pages = decode("v115@vhAAgH", &pieces).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert!(pages[0].field == EMPTY_BOARD);
+        assert!(pages[0].piece.is_none());
+        assert_eq!(encode(&pages, &pieces), "v115@vhAAgH");
+    }
+
+    #[test]
+    fn decode_garbage_and_piece() {
+        // a full garbage row at the bottom, a T in spawn orientation locked on top of it
+        let data = "v115@bhJ8JeVLJ";
+        let pieces = Pieces::default();
+        let pages = decode(data, &pieces).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert!(pages[0].field == parse_board("xxxxxxxxxx").unwrap());
+        assert_eq!(
+            cells(&pieces, &pages[0].piece),
+            Some(vec![(3, 1), (4, 1), (4, 2), (5, 1)])
+        );
+        assert!(pages[0].lock);
+
+        // the full garbage row is cleared when the T locks
+        let t = cell_from_kind(kind_from_piece_type(PieceType::T));
+        let mut board = EMPTY_BOARD;
+        board[0][3] = t;
+        board[0][4] = t;
+        board[0][5] = t;
+        board[1][4] = t;
+        assert!(decode_board(data).unwrap() == board);
+    }
+
+    // single pieces on an empty board, encoded with the coordinate rules of the reference implementation
+    // (tetris-fumen), with the cells they cover there
+    #[test]
+    fn decode_legacy_offsets() {
+        let pieces = Pieces::default();
+        let cases: &[(&str, [(i32, i32); 4])] = &[
+            // Z spawn at (4, 1)
+            ("v115@vhAULJ", [(3, 2), (4, 1), (4, 2), (5, 1)]),
+            // Z right, stored at (5, 1)
+            ("v115@vhAsLJ", [(4, 0), (4, 1), (5, 1), (5, 2)]),
+            ("v115@vhAcLJ", [(3, 0), (3, 1), (4, 1), (4, 2)]),
+            // S spawn, stored at (4, 2)
+            ("v115@vhAXGJ", [(3, 1), (4, 1), (4, 2), (5, 2)]),
+            ("v115@vhAPLJ", [(4, 1), (4, 2), (5, 0), (5, 1)]),
+            ("v115@vhAfLJ", [(3, 1), (3, 2), (4, 0), (4, 1)]),
+            // I left and right, stored at (4, 2)
+            ("v115@vhAZGJ", [(4, 0), (4, 1), (4, 2), (4, 3)]),
+            ("v115@vhAJGJ", [(4, 0), (4, 1), (4, 2), (4, 3)]),
+            // O left stored at (3, 2), O spawn at (4, 2)
+            ("v115@vhA7FJ", [(3, 1), (3, 2), (4, 1), (4, 2)]),
+            ("v115@vhATGJ", [(4, 1), (4, 2), (5, 1), (5, 2)]),
+        ];
+        for (data, expected) in cases.iter() {
+            let pages = decode(data, &pieces).unwrap();
+            assert_eq!(
+                cells(&pieces, &pages[0].piece),
+                Some(expected.to_vec()),
+                "{}",
+                data
+            );
+            // and back
+            assert_eq!(
+                cells(
+                    &pieces,
+                    &decode(&encode(&pages, &pieces), &pieces).unwrap()[0].piece
+                ),
+                Some(expected.to_vec()),
+                "{}",
+                data
+            );
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        let pieces = Pieces::default();
+        assert!(decode("v110@vhAAgH", &pieces).is_err());
+        assert!(decode("v115@vhA", &pieces).is_err());
+        assert!(decode("v115@vh*AgH", &pieces).is_err());
+    }
+}
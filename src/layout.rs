@@ -0,0 +1,258 @@
+use super::{
+    config::Config, field::Cell, mode::ModeText, theme::Theme, CalloutText, PieceType, Pieces,
+    ScoreText, State,
+};
+use bevy::{
+    prelude::*,
+    render::{camera::Camera, render_graph::base::camera::CAMERA2D},
+};
+
+// size of a cell in world units
+pub const CELL: f32 = 32.0;
+const FIELD_WIDTH: f32 = 10.0;
+const FIELD_HEIGHT: f32 = 20.0;
+// room for a piece next to the field, in cells
+const BOX_WIDTH: f32 = 6.0;
+// vertical distance between the pieces of the next queue, in cells
+const NEXT_SPACING: f32 = 3.0;
+const MARGIN: f32 = 5.0;
+// height of a line of hud text
+const TEXT_LINE: f32 = 45.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+// How the world is scaled to the window. `Integer` only uses whole multiples of the tile size of the theme, so
+// that the Game Boy tiles stay crisp (at the cost of a border around everything)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Zoom {
+    Fit,
+    Integer,
+}
+
+impl Zoom {
+    // `bevris --zoom integer`, or `zoom integer` in the config file
+    fn from_args(config: &Config) -> Self {
+        let args = std::env::args().collect::<Vec<_>>();
+        let name = args
+            .iter()
+            .position(|arg| arg == "--zoom")
+            .and_then(|i| args.get(i + 1))
+            .map(|name| name.as_str())
+            .or_else(|| config.get("zoom"));
+        match name {
+            None | Some("fit") => Zoom::Fit,
+            Some("integer") => Zoom::Integer,
+            Some(name) => {
+                println!("unknown zoom: {}. using fit", name);
+                Zoom::Fit
+            }
+        }
+    }
+}
+
+// the part of the layout a theme can change
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayoutStyle {
+    // number of pieces shown in the next queue
+    pub next_pieces: usize,
+    // side of the field the score, level, lines and time go to
+    pub stats: Side,
+}
+
+// Where everything goes. The field stays at the world origin (cell x, y at x * 32, y * 32), the hold box goes to
+// the left of it, the next queue to the right and the camera is centered on all of it and zoomed to fit the
+// window. The text is placed in screen pixels (from the top left corner of the window) next to the field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+    window: (f32, f32),
+    style: LayoutStyle,
+    pub camera: Vec3,
+    // window pixels per world unit
+    pub zoom: f32,
+    pub hold: Vec3,
+    pub next: Vec<Vec3>,
+    stats: (f32, f32),
+    callout: (f32, f32),
+}
+
+impl Layout {
+    pub fn new(window: (f32, f32), theme: &Theme, zoom_mode: Zoom) -> Self {
+        let style = theme.layout;
+        // the outer edges of the field (cells are centered on their position)
+        let left = -CELL / 2.0;
+        let right = left + FIELD_WIDTH * CELL;
+        let bottom = -CELL / 2.0;
+        let top = bottom + FIELD_HEIGHT * CELL;
+
+        let hold = Vec3::new(left - BOX_WIDTH * CELL / 2.0, top - 3.0 * CELL, 0.0);
+        let next = (0..style.next_pieces)
+            .map(|i| {
+                Vec3::new(
+                    right + BOX_WIDTH * CELL / 2.0,
+                    top - (3.0 + NEXT_SPACING * i as f32) * CELL,
+                    0.0,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // the hold box, field and next queue with a cell of room around them
+        let width = FIELD_WIDTH + 2.0 * BOX_WIDTH + 2.0;
+        let height = FIELD_HEIGHT + 2.0;
+        let mut zoom = (window.0 / (width * CELL)).min(window.1 / (height * CELL));
+        let mut camera = Vec3::new((left + right) / 2.0, (bottom + top) / 2.0, 1.0);
+        if zoom_mode == Zoom::Integer {
+            // whole window pixels per tile pixel, and tile pixels starting on window pixels
+            let pixel = theme.tile_scale();
+            zoom = (zoom * pixel).floor().max(1.0) / pixel;
+            let snap =
+                |center: f32, size: f32| (size / 2.0 - (size / 2.0 - center * zoom).round()) / zoom;
+            camera = Vec3::new(snap(camera.x(), window.0), snap(camera.y(), window.1), 1.0);
+        }
+
+        let to_screen = |x: f32, y: f32| {
+            (
+                ((x - camera.x()) * zoom + window.0 / 2.0).max(MARGIN),
+                (window.1 / 2.0 - (y - camera.y()) * zoom).max(MARGIN),
+            )
+        };
+        // below the hold box or below the last piece of the next queue
+        let (stats, callout) = match style.stats {
+            Side::Left => (
+                to_screen(left - BOX_WIDTH * CELL, hold.y() - 3.0 * CELL),
+                to_screen(left - BOX_WIDTH * CELL, bottom + 3.0 * CELL),
+            ),
+            Side::Right => (
+                to_screen(
+                    right + CELL,
+                    top - (3.0 + NEXT_SPACING * style.next_pieces as f32) * CELL,
+                ),
+                to_screen(right + CELL, bottom + 3.0 * CELL),
+            ),
+        };
+
+        Layout {
+            window,
+            style,
+            camera,
+            zoom,
+            hold,
+            next,
+            stats,
+            callout,
+        }
+    }
+
+    // world position of a point on the screen (in pixels from the top left corner of the window)
+    pub fn to_world(&self, (x, y): (f32, f32)) -> Vec3 {
+        Vec3::new(
+            (x - self.window.0 / 2.0) / self.zoom + self.camera.x(),
+            self.camera.y() - (y - self.window.1 / 2.0) / self.zoom,
+            0.0,
+        )
+    }
+}
+
+// what the hold box and the next queue show
+#[derive(Clone, PartialEq)]
+pub struct HudPieces {
+    hold: Option<PieceType>,
+    queue: Vec<PieceType>,
+    layout: Layout,
+}
+
+impl HudPieces {
+    pub fn new(state: &State, queue: Vec<PieceType>, layout: &Layout) -> Self {
+        // hidden while paused, like the board
+        if state.paused {
+            return HudPieces {
+                hold: None,
+                queue: Vec::new(),
+                layout: layout.clone(),
+            };
+        }
+        HudPieces {
+            hold: state.hold,
+            queue,
+            layout: layout.clone(),
+        }
+    }
+
+    // world position and cell of all blocks
+    pub fn blocks(&self, pieces: &Pieces) -> Vec<(Vec3, Cell)> {
+        let hold = self.hold.iter().map(|t| (self.layout.hold, t));
+        let next = self.layout.next.iter().cloned().zip(self.queue.iter());
+        hold.chain(next)
+            .flat_map(|(pos, t)| {
+                pieces.get_solid_base(t)[0]
+                    .iter()
+                    .map(|(x, y, c)| (pos + Vec3::new(*x as f32 * CELL, *y as f32 * CELL, 1.0), *c))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+}
+
+// recomputes the layout when the window is resized or the theme switched and moves the camera and the text
+fn layout_system(
+    windows: Res<Windows>,
+    theme: Res<Theme>,
+    zoom: Res<Zoom>,
+    mut layout: ResMut<Layout>,
+    mut camera_query: Query<(&Camera, &mut Transform)>,
+    mut score_query: Query<(&ScoreText, &mut Style)>,
+    mut mode_query: Query<(&ModeText, &mut Style)>,
+    mut callout_query: Query<(&CalloutText, &mut Style)>,
+) {
+    let window = match windows.get_primary() {
+        // nothing to fit into while minimized
+        Some(window) if window.width > 0 && window.height > 0 => {
+            (window.width as f32, window.height as f32)
+        }
+        _ => return,
+    };
+    let new_layout = Layout::new(window, &theme, *zoom);
+    if *layout == new_layout {
+        return;
+    }
+    *layout = new_layout;
+
+    // only the board camera, the ui camera stays where it is
+    for (camera, mut transform) in &mut camera_query.iter() {
+        if camera.name.as_deref() != Some(CAMERA2D) {
+            continue;
+        }
+        transform.set_translation(layout.camera);
+        transform.set_scale(1.0 / layout.zoom);
+    }
+    let place = |style: &mut Style, (left, top): (f32, f32)| {
+        style.position.left = Val::Px(left);
+        style.position.top = Val::Px(top);
+        style.position.bottom = Val::Undefined;
+    };
+    let (left, top) = layout.stats;
+    for (_, mut style) in &mut score_query.iter() {
+        place(&mut *style, (left, top));
+    }
+    for (_, mut style) in &mut mode_query.iter() {
+        place(&mut *style, (left, top + TEXT_LINE));
+    }
+    for (_, mut style) in &mut callout_query.iter() {
+        place(&mut *style, layout.callout);
+    }
+}
+
+pub struct LayoutPlugin;
+
+impl Plugin for LayoutPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // starts out of date, the first frame places the camera and the text
+        let config = Config::load();
+        app.add_resource(Zoom::from_args(&config))
+            .add_resource(Layout::new((0.0, 0.0), &Theme::default(), Zoom::Fit))
+            .add_system(layout_system.system());
+    }
+}
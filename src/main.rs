@@ -2,32 +2,52 @@ use bevy::{
     diagnostic::{FrameTimeDiagnosticsPlugin, PrintDiagnosticsPlugin},
     input::keyboard::{ElementState as KeyboardElementState, KeyboardInput},
     prelude::*,
-    render::pass::ClearColor,
 };
 use rand::prelude::*;
-
+use std::collections::VecDeque;
+
+mod bindings;
+mod bitmap_font;
+mod clear_animation;
+mod config;
+mod dirty;
+mod events;
 mod field;
+mod fumen;
+mod layout;
+mod mode;
+mod music;
+mod pause;
 mod pieces;
-
-use field::Playfield;
+mod puzzle;
+mod sound;
+mod theme;
+mod timestep;
+
+use bindings::{Action, Bindings};
+use events::GameEvent;
+use field::{Cell, Playfield};
+use mode::GameMode;
 use pieces::{Piece, PieceType, Pieces};
+use timestep::FixedTimestep;
+
+// delayed auto shift and soft drop speed, in simulation ticks (roughly NES timing)
+const DAS_DELAY: u32 = 16;
+const DAS_REPEAT: u32 = 6;
+const SOFT_DROP_FRAMES: u32 = 3;
+const LINE_TRANSITION_FRAMES: u32 = 30;
+const CALLOUT_TICKS: u64 = 120;
 
 fn main() {
-    let mut state = State {
-        timer: Timer::new(std::time::Duration::from_micros(16666) * 3, true),
-        fast_timer: Timer::new(std::time::Duration::from_micros(16666) * 3, true),
-        ..Default::default()
-    };
+    let mut state = State::default();
     state.update_speed();
     App::build()
         .add_default_plugins()
-        .add_resource(Scoreboard { _score: 0 })
-        .add_resource(ClearColor(Color::rgb(0.7, 0.7, 0.7)))
+        .init_resource::<Scoreboard>()
         .add_resource(state)
         .init_resource::<PieceBag>()
         .init_resource::<Pieces>()
         .add_startup_system(setup.system())
-        // .add_startup_system(setup_music.system())
         .add_plugin(BevrisPlugin)
         .run();
 }
@@ -36,11 +56,8 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>, mut piece_bag:
     // Add the game's entities to our world
     commands
         // cameras
-        .spawn(Camera2dComponents {
-            transform: Transform::from_translation(Vec3::new(32f32 * 5f32, 32f32 * 10f32, 1.0)),
-            ..Default::default()
-        })
-        // .spawn(UiCameraComponents::default()) // FIXME: the UI camera causes some wgpu internal crash if TextureSheetSprites are used
+        // placed and zoomed by the layout. the ui camera comes later, see field::ui_camera_system
+        .spawn(Camera2dComponents::default())
         // scoreboard
         .spawn(TextComponents {
             text: Text {
@@ -62,6 +79,28 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>, mut piece_bag:
             },
             ..Default::default()
         })
+        .with(ScoreText)
+        .spawn(TextComponents {
+            text: Text {
+                font: asset_server.load("assets/fonts/FiraSans-Bold.ttf").unwrap(),
+                value: String::new(),
+                style: TextStyle {
+                    color: Color::rgb(0.8, 0.6, 0.0),
+                    font_size: 40.0,
+                },
+            },
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Val::Px(5.0),
+                    left: Val::Px(5.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with(CalloutText)
         .spawn((
             piece_bag.next(),
             Piece {
@@ -72,240 +111,748 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>, mut piece_bag:
         ));
 }
 
-// fn setup_music(asset_server: Res<AssetServer>, audio_output: Res<AudioOutput>) {
-//     let music = asset_server
-//         .load("assets/sounds/Windless Slopes.mp3")
-//         .unwrap();
-//     audio_output.play(music);
-// }
-
+#[derive(Default)]
 struct Scoreboard {
-    _score: usize,
+    score: usize,
 }
 
-fn get_color(t: &PieceType) -> usize {
-    match *t {
-        PieceType::I => 2,
-        PieceType::L => 3,
-        PieceType::J => 4,
-        PieceType::S => 5,
-        PieceType::Z => 6,
-        PieceType::O => 7,
-        PieceType::T => 8,
+impl Scoreboard {
+    // NES style points per number of cleared lines, with guideline style bonuses for t-spins, back-to-back,
+    // combos and perfect clears on top. Everything is multiplied by level.
+    fn add_clear(&mut self, clear: &LineClear, level: usize) {
+        let lines = clear.lines.min(4);
+        let mut points = if clear.tspin {
+            [400, 800, 1200, 1600, 1600][lines]
+        } else {
+            [0, 40, 100, 300, 1200][lines]
+        };
+        if clear.b2b > 1 {
+            points = points * 3 / 2;
+        }
+        points += 50 * clear.combo.saturating_sub(1);
+        if clear.perfect_clear {
+            points += [0, 800, 1200, 1800, 2000][lines];
+        }
+        self.score += points * (level + 1);
     }
 }
 
-fn player_input_system(
+struct ScoreText;
+
+fn scoreboard_system(
+    scoreboard: Res<Scoreboard>,
+    state: Res<State>,
+    mut query: Query<(&ScoreText, &mut Text)>,
+) {
+    let mut value = format!("Score: {}", scoreboard.score);
+    if state.combo > 1 {
+        value.push_str(&format!(" Combo {}", state.combo - 1));
+    }
+    if state.b2b > 1 {
+        value.push_str(&format!(" B2B {}", state.b2b - 1));
+    }
+    for (_, mut text) in &mut query.iter() {
+        text.value = value.clone();
+    }
+}
+
+// short message about special clears, shown for a while after the piece locked
+struct Callout {
+    text: String,
+    until: u64,
+}
+
+struct CalloutText;
+
+fn callout_system(state: Res<State>, mut query: Query<(&CalloutText, &mut Text)>) {
+    let value = match state.callout {
+        _ if state.paused => "Paused".to_string(),
+        Some(ref callout) if state.ticks < callout.until => callout.text.clone(),
+        _ => String::new(),
+    };
+    for (_, mut text) in &mut query.iter() {
+        text.value = value.clone();
+    }
+}
+
+fn collides(playfield: &Playfield, pieces: &Pieces, t: &PieceType, p: &Piece) -> bool {
+    pieces
+        .get_solid(t, p)
+        .iter()
+        .map(|(x, y, _)| {
+            *x < 0
+                || *x >= 10
+                || *y < 0
+                || *y >= 22
+                || playfield.get(*x as usize, *y as usize).is_solid()
+        })
+        .any(|x| x)
+}
+
+// runs the game logic for all simulation ticks that are due this frame
+fn simulation_system(
+    timestep: Res<FixedTimestep>,
+    mode: Res<GameMode>,
     mut playfield: ResMut<Playfield>,
     mut state: ResMut<State>,
+    mut piece_bag: ResMut<PieceBag>,
+    mut scoreboard: ResMut<Scoreboard>,
     keyboard_input_events: Res<Events<KeyboardInput>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    bindings: Res<Bindings>,
     pieces: Res<Pieces>,
-    mut query: Query<(&PieceType, &mut Piece)>,
-    mut query_line_transitions: Query<&LineTransition>,
+    mut query: Query<(&mut PieceType, &mut Piece)>,
 ) {
-    // crappy way to block system if some entity exists... is this a common ecs pattern or anitpattern?
-    if query_line_transitions.iter().iter().len() != 0 {
+    // key presses are queued until the next tick (there may be frames without any tick at high frame rates)
+    let mut key_presses = Vec::new();
+    for event in state.event_reader.iter(&keyboard_input_events) {
+        if let KeyboardInput {
+            key_code: Some(key_code),
+            state: KeyboardElementState::Pressed,
+            ..
+        } = event
+        {
+            key_presses.push(*key_code);
+        }
+    }
+    state.key_presses.extend(key_presses);
+    if state.waiting || state.paused {
+        state.key_presses.clear();
         return;
     }
 
-    for (t, mut p) in &mut query.iter() {
-        // delete old pos
-        for (x, y, _) in pieces.get_solid(t, &*p).iter() {
-            playfield.field[*y as usize][*x as usize] = 0;
+    for _ in 0..timestep.ticks {
+        if state.finished {
+            break;
         }
-        let mut pnew = p.clone();
-        // update input
-        for event in state.event_reader.iter(&keyboard_input_events) {
-            // println!("{:?}", event);
-            match event {
-                KeyboardInput {
-                    key_code: Some(key_code),
-                    state: KeyboardElementState::Pressed,
-                    ..
-                } => match key_code {
-                    KeyCode::Left => pnew.x -= 1,
-                    KeyCode::Right => pnew.x += 1,
-                    KeyCode::Space | KeyCode::Up => pnew.rot += 1,
-                    _ => (),
-                },
-                _ => (),
+        state.ticks += 1;
+        if state.line_transition.is_some() {
+            check_lines(&mut playfield, &mut state);
+            if state.line_transition.is_none() {
+                for (t, p) in &mut query.iter() {
+                    place_piece(&mut playfield, &mut state, &pieces, &t, &p);
+                }
+            }
+        } else {
+            for (mut t, mut p) in &mut query.iter() {
+                player_input(
+                    &mut playfield,
+                    &mut state,
+                    &mut piece_bag,
+                    &keyboard_input,
+                    &bindings,
+                    &pieces,
+                    &mut t,
+                    &mut p,
+                );
+                if state.topped_out {
+                    continue;
+                }
+                piece_update(
+                    &mut playfield,
+                    &mut state,
+                    &mut piece_bag,
+                    &mut scoreboard,
+                    &keyboard_input,
+                    &bindings,
+                    &pieces,
+                    &mut t,
+                    &mut p,
+                );
+                if state.line_transition.is_none() && !state.topped_out {
+                    if let Some(messiness) = mode.rising_garbage(&mut state) {
+                        rise_garbage(&mut playfield, &mut state, &pieces, &t, &mut p, messiness);
+                    }
+                }
             }
         }
-
-        let illegal_user_move = pieces
-            .get_solid(&t, &pnew)
-            .iter()
-            .map(|(x, y, _)| {
-                *x < 0
-                    || *x >= 10
-                    || *y < 0
-                    || *y >= 22
-                    || playfield.field[*y as usize][*x as usize] != 0
-            })
-            .any(|x| x);
-
-        if !illegal_user_move {
-            *p = pnew;
+        // presses during a line transition are dropped
+        state.key_presses.clear();
+
+        if state.topped_out || mode.is_finished(&state, &playfield) {
+            state.finished = true;
+            let topped_out = state.topped_out;
+            state
+                .events
+                .push(GameEvent::GameOver(events::GameOver { topped_out }));
         }
     }
 }
 
-fn piece_update_system(
-    mut commands: Commands,
-    time: Res<Time>,
-    mut playfield: ResMut<Playfield>,
-    mut state: ResMut<State>,
-    mut piece_bag: ResMut<PieceBag>,
-    keyboard_input: Res<Input<KeyCode>>,
-    pieces: Res<Pieces>,
-    mut query: Query<(Entity, &PieceType, &mut Piece)>,
-    mut query_line_transitions: Query<&LineTransition>,
+fn player_input(
+    playfield: &mut Playfield,
+    state: &mut State,
+    piece_bag: &mut PieceBag,
+    keyboard_input: &Input<KeyCode>,
+    bindings: &Bindings,
+    pieces: &Pieces,
+    t: &mut PieceType,
+    p: &mut Piece,
 ) {
-    if query_line_transitions.iter().iter().len() != 0 {
-        return;
+    // delete old pos
+    for (x, y, _) in pieces.get_solid(t, p).iter() {
+        playfield.set(*x as usize, *y as usize, Cell::EMPTY);
     }
 
-    state.timer.tick(time.delta_seconds);
-    state.fast_timer.tick(time.delta_seconds);
-    for (ent, t, mut p) in &mut query.iter() {
-        let fast_move = if keyboard_input.pressed(KeyCode::Down) {
-            if state.fast_generation.is_none() {
-                state.fast_generation = Some(ent);
+    let actions = std::mem::take(&mut state.key_presses)
+        .into_iter()
+        .flat_map(|key_code| bindings.actions(key_code))
+        .collect::<Vec<_>>();
+    for action in actions {
+        let mut pnew = p.clone();
+        match action {
+            Action::Left => {
+                pnew.x -= 1;
+                state.das_frames = 0;
             }
-            state.fast_generation == Some(ent)
-        } else {
-            state.fast_generation = None;
-            false
-        };
+            Action::Right => {
+                pnew.x += 1;
+                state.das_frames = 0;
+            }
+            // the rotation is taken modulo the number of rotations of the piece
+            Action::RotateCw => pnew.rot += 1,
+            Action::RotateCcw => pnew.rot += 3,
+            Action::Rotate180 => pnew.rot += 2,
+            Action::HardDrop => {
+                hard_drop(playfield, state, pieces, t, p);
+                // the piece locks in piece_update
+                return;
+            }
+            Action::Hold => {
+                hold(playfield, state, piece_bag, pieces, t, p);
+                if state.topped_out {
+                    return;
+                }
+                continue;
+            }
+            _ => continue,
+        }
+        if !collides(playfield, pieces, t, &pnew) {
+            let (dx, rotated) = (pnew.x - p.x, pnew.rot != p.rot);
+            state.rotated = rotated;
+            *p = pnew;
+            state.events.push(if rotated {
+                GameEvent::PieceRotated(events::PieceRotated {
+                    piece_type: *t,
+                    piece: p.clone(),
+                })
+            } else {
+                moved(t, p, dx, 0, false)
+            });
+        }
+    }
 
+    // auto repeat while left / right is held
+    let shift = match (
+        bindings.pressed(keyboard_input, Action::Left),
+        bindings.pressed(keyboard_input, Action::Right),
+    ) {
+        (true, false) => -1,
+        (false, true) => 1,
+        _ => 0,
+    };
+    if shift == 0 {
+        state.das_frames = 0;
+        return;
+    }
+    state.das_frames += 1;
+    if state.das_frames >= DAS_DELAY && (state.das_frames - DAS_DELAY) % DAS_REPEAT == 0 {
         let mut pnew = p.clone();
-        let do_move =
-            (!fast_move && state.timer.finished) || (fast_move && state.fast_timer.finished);
-        state.timer.finished = false;
-        state.fast_timer.finished = false;
-        if do_move {
-            pnew.y -= 1;
-            state.timer.finished = false;
+        pnew.x += shift;
+        if !collides(playfield, pieces, t, &pnew) {
+            state.rotated = false;
+            *p = pnew;
+            state.events.push(moved(t, p, shift, 0, false));
         }
+    }
+}
 
-        let on_ground = pieces
-            .get_solid(&t, &pnew)
-            .iter()
-            .map(|(x, y, _)| *y < 0 || playfield.field[*y as usize][*x as usize] != 0)
-            .any(|x| x);
+fn moved(t: &PieceType, p: &Piece, dx: i32, dy: i32, soft_drop: bool) -> GameEvent {
+    GameEvent::PieceMoved(events::PieceMoved {
+        piece_type: *t,
+        piece: p.clone(),
+        dx,
+        dy,
+        soft_drop,
+    })
+}
 
-        // draw new pos
-        if !on_ground {
-            *p = pnew;
+// moves the piece down as far as it goes, it locks on the next piece update
+fn hard_drop(
+    playfield: &Playfield,
+    state: &mut State,
+    pieces: &Pieces,
+    t: &PieceType,
+    p: &mut Piece,
+) {
+    let mut pnew = p.clone();
+    pnew.y -= 1;
+    while !collides(playfield, pieces, t, &pnew) {
+        pnew.y -= 1;
+    }
+    pnew.y += 1;
+    let dy = pnew.y - p.y;
+    *p = pnew;
+    state.hard_drop = true;
+    if dy != 0 {
+        state.rotated = false;
+        state.events.push(moved(t, p, 0, dy, false));
+    }
+}
+
+// swaps the active piece with the held one (or the next one from the bag), once per piece
+fn hold(
+    playfield: &Playfield,
+    state: &mut State,
+    piece_bag: &mut PieceBag,
+    pieces: &Pieces,
+    t: &mut PieceType,
+    p: &mut Piece,
+) {
+    if state.hold_used {
+        return;
+    }
+    state.hold_used = true;
+    let held = *t;
+    match state.hold.replace(held) {
+        Some(hold) => {
+            *t = hold;
+            *p = Piece {
+                x: 3,
+                y: 16,
+                rot: 0,
+            };
         }
+        // the first hold takes the next piece from the bag, like a lock does
+        None => spawn_piece(state, piece_bag, t, p),
+    }
+    state.rotated = false;
+    state.events.push(GameEvent::PieceHeld(events::PieceHeld {
+        held,
+        piece_type: *t,
+    }));
+    if collides(playfield, pieces, t, p) {
+        state.topped_out = true;
+    }
+}
 
-        for (x, y, c) in pieces.get_solid(t, &*p).iter() {
-            playfield.field[*y as usize][*x as usize] = *c as u8;
+fn piece_update(
+    playfield: &mut Playfield,
+    state: &mut State,
+    piece_bag: &mut PieceBag,
+    scoreboard: &mut Scoreboard,
+    keyboard_input: &Input<KeyCode>,
+    bindings: &Bindings,
+    pieces: &Pieces,
+    t: &mut PieceType,
+    p: &mut Piece,
+) {
+    // soft drop only applies to the piece that was active when it was pressed
+    let fast_move = if bindings.pressed(keyboard_input, Action::SoftDrop) {
+        if state.fast_generation.is_none() {
+            state.fast_generation = Some(state.generation);
         }
+        state.fast_generation == Some(state.generation)
+    } else {
+        state.fast_generation = None;
+        false
+    };
 
-        if on_ground {
-            let next = piece_bag.next();
-            // check_lines_system(&mut *playfield);
-            // println!("hit ground. next: {:?}", next);
+    state.gravity_frames += 1;
+    let gravity = state.gravity_frames >= state.frames_per_row;
+    if gravity {
+        state.gravity_frames = 0;
+    }
+    state.fast_frames += 1;
+    let fast = state.fast_frames >= SOFT_DROP_FRAMES;
+    if fast {
+        state.fast_frames = 0;
+    }
 
-            // let line_transition = LineTransition{ }
+    let mut pnew = p.clone();
+    let do_move = (!fast_move && gravity) || (fast_move && fast) || state.hard_drop;
+    state.hard_drop = false;
+    if do_move {
+        pnew.y -= 1;
+    }
 
-            let mut eliminate = Vec::new();
-            for (y, line) in playfield.field.iter().enumerate() {
-                if line.iter().all(|x| *x != 0) {
-                    eliminate.push(y);
-                    state.lines += 1;
-                }
+    let on_ground = pieces
+        .get_solid(t, &pnew)
+        .iter()
+        .map(|(x, y, _)| *y < 0 || playfield.get(*x as usize, *y as usize).is_solid())
+        .any(|x| x);
+
+    // draw new pos
+    if !on_ground {
+        *p = pnew;
+        if do_move {
+            state.rotated = false;
+            if fast_move {
+                scoreboard.score += 1;
             }
+            state.events.push(moved(t, p, 0, -1, fast_move));
+        }
+    }
 
-            if !eliminate.is_empty() {
-                state.update_speed();
-                commands.spawn((LineTransition {
-                    timer: Timer::new(std::time::Duration::from_millis(500), false),
-                    to_eliminate: eliminate,
-                },));
+    for (x, y, c) in pieces.get_solid(t, p).iter() {
+        playfield.set(*x as usize, *y as usize, *c);
+    }
+
+    if on_ground {
+        let level = state.level();
+        let tspin = is_tspin(playfield, t, p, state.rotated);
+        let mut field = *playfield.board();
+        for (x, y, _) in pieces.get_solid(t, p).iter() {
+            field[*y as usize][*x as usize] = Cell::EMPTY;
+        }
+        state.placements.push(fumen::Page {
+            field,
+            piece: Some((*t, p.clone())),
+            lock: true,
+        });
+        state
+            .events
+            .push(GameEvent::PieceLocked(events::PieceLocked {
+                piece_type: *t,
+                piece: p.clone(),
+            }));
+        state.hold_used = false;
+        let mut eliminate = Vec::new();
+        for (y, line) in playfield.board().iter().enumerate() {
+            if line.iter().all(|x| x.is_solid()) {
+                eliminate.push(y);
+                state.lines += 1;
             }
-            commands
-                .spawn((
-                    next,
-                    Piece {
-                        x: 3,
-                        y: 16,
-                        rot: 0,
+        }
+
+        // i.e. every row is either cleared or empty
+        let perfect_clear = !eliminate.is_empty()
+            && playfield
+                .board()
+                .iter()
+                .all(|line| line.iter().all(|c| c.is_solid()) || line.iter().all(|c| c.is_empty()));
+        let clear = state.record_clear(eliminate.len(), tspin, perfect_clear);
+        state.rotated = false;
+        if !eliminate.is_empty() {
+            state
+                .events
+                .push(GameEvent::LinesCleared(events::LinesCleared {
+                    rows: eliminate.clone(),
+                    spin: if tspin {
+                        events::Spin::TSpin
+                    } else {
+                        events::Spin::None
                     },
-                ))
-                .despawn(ent);
+                    combo: state.combo,
+                    b2b: clear.b2b,
+                    perfect_clear,
+                }));
+        }
+
+        scoreboard.add_clear(&clear, level);
+        state.attack += clear.attack();
+        if perfect_clear {
+            state.perfect_clears += 1;
+        }
+        if let Some(text) = clear.callout() {
+            state.callout = Some(Callout {
+                text,
+                until: state.ticks + CALLOUT_TICKS,
+            });
+        }
+
+        if !eliminate.is_empty() {
+            state.update_speed();
+            if state.level() > level {
+                let level = state.level();
+                state
+                    .events
+                    .push(GameEvent::LevelUp(events::LevelUp { level }));
+            }
+            state.line_transition = Some(LineTransition {
+                frames: 0,
+                to_eliminate: eliminate,
+            });
+        }
+        // the piece entity is recycled for the next piece, so it can keep moving within the same frame
+        spawn_piece(state, piece_bag, t, p);
+
+        // otherwise the piece is placed once the cleared lines are gone
+        if state.line_transition.is_none() {
+            place_piece(playfield, state, pieces, t, p);
         }
     }
 }
 
-struct LineTransition {
-    timer: Timer,
-    to_eliminate: Vec<usize>,
+// 3-corner rule: the last successful move was a rotation and at least three of the four cells diagonal to the
+// center of the T are blocked (the walls and the floor count as blocked)
+fn is_tspin(playfield: &Playfield, t: &PieceType, p: &Piece, rotated: bool) -> bool {
+    if *t != PieceType::T || !rotated {
+        return false;
+    }
+    // the center is at the same offset in all rotations
+    let (cx, cy) = (p.x + 1, p.y + 2);
+    [(-1, -1), (1, -1), (-1, 1), (1, 1)]
+        .iter()
+        .filter(|(dx, dy)| {
+            let (x, y) = (cx + dx, cy + dy);
+            x < 0 || x >= 10 || y < 0 || y >= 22 || playfield.get(x as usize, y as usize).is_solid()
+        })
+        .count()
+        >= 3
 }
 
-fn check_lines_system(
-    mut commands: Commands,
-    mut playfield: ResMut<Playfield>,
-    time: Res<Time>,
-    mut query: Query<(Entity, &mut LineTransition)>,
+// takes the next piece from the bag as the active piece, every piece taken counts as a new generation
+fn spawn_piece(state: &mut State, piece_bag: &mut PieceBag, t: &mut PieceType, p: &mut Piece) {
+    *t = piece_bag.next();
+    *p = Piece {
+        x: 3,
+        y: 16,
+        rot: 0,
+    };
+    state.generation += 1;
+    state
+        .events
+        .push(GameEvent::PieceSpawned(events::PieceSpawned {
+            piece_type: *t,
+        }));
+}
+
+// draws a freshly spawned piece into the field, so that the active piece is always part of the field between ticks
+fn place_piece(
+    playfield: &mut Playfield,
+    state: &mut State,
+    pieces: &Pieces,
+    t: &PieceType,
+    p: &Piece,
 ) {
-    for (ent, mut lt) in &mut query.iter() {
-        lt.timer.tick(time.delta_seconds);
+    if collides(playfield, pieces, t, p) {
+        state.topped_out = true;
+        return;
+    }
+    for (x, y, c) in pieces.get_solid(t, p).iter() {
+        playfield.set(*x as usize, *y as usize, *c);
+    }
+}
 
-        if lt.timer.finished {
-            commands.despawn(ent);
+// pushes a garbage row in from the bottom. the active piece is moved up out of the way if necessary
+fn rise_garbage(
+    playfield: &mut Playfield,
+    state: &mut State,
+    pieces: &Pieces,
+    t: &PieceType,
+    p: &mut Piece,
+    messiness: f32,
+) {
+    for (x, y, _) in pieces.get_solid(t, p).iter() {
+        playfield.set(*x as usize, *y as usize, Cell::EMPTY);
+    }
+    if !playfield.push_garbage(1, messiness, &mut rand::thread_rng()) {
+        state.topped_out = true;
+    }
 
-            for line in lt.to_eliminate.iter().rev() {
-                for y in *line..21 {
-                    // TODO: bubble up with swap?
-                    // std::mem::swap(&mut playfield.field[y], &mut playfield.field[y + 1]);
-                    playfield.field[y] = playfield.field[y + 1].clone();
-                }
-                playfield.field[21] = [0u8; 10];
-            }
+    let mut pnew = p.clone();
+    while pnew.y < 22 && collides(playfield, pieces, t, &pnew) {
+        pnew.y += 1;
+    }
+    if collides(playfield, pieces, t, &pnew) {
+        state.topped_out = true;
+        return;
+    }
+    *p = pnew;
+    for (x, y, c) in pieces.get_solid(t, p).iter() {
+        playfield.set(*x as usize, *y as usize, *c);
+    }
+}
+
+// outcome of the last piece lock
+#[derive(Debug, Clone, Copy)]
+struct LineClear {
+    lines: usize,
+    tspin: bool,
+    perfect_clear: bool,
+    // number of consecutive clears including this one (0 if nothing was cleared)
+    combo: usize,
+    // number of consecutive difficult clears (tetrises and t-spins) including this one, 0 for other clears
+    b2b: usize,
+}
+
+impl LineClear {
+    // lines of garbage this clear would send to an opponent (guideline table)
+    fn attack(&self) -> usize {
+        if self.lines == 0 {
+            return 0;
         }
+        let mut attack = if self.tspin {
+            2 * self.lines.min(3)
+        } else {
+            [0, 0, 1, 2, 4][self.lines.min(4)]
+        };
+        if self.b2b > 1 {
+            attack += 1;
+        }
+        let combo_table = [0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 4];
+        attack += combo_table.get(self.combo - 1).cloned().unwrap_or(5);
+        if self.perfect_clear {
+            attack += 10;
+        }
+        attack
+    }
+
+    fn callout(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if self.b2b > 1 {
+            parts.push("B2B".to_string());
+        }
+        let name = ["", "SINGLE", "DOUBLE", "TRIPLE", "TETRIS"][self.lines.min(4)];
+        if self.tspin {
+            parts.push(format!("T-SPIN {}", name).trim_end().to_string());
+        } else if self.lines == 4 {
+            parts.push(name.to_string());
+        }
+        if self.combo > 1 {
+            parts.push(format!("{} COMBO", self.combo - 1));
+        }
+        if self.perfect_clear {
+            parts.push("PERFECT CLEAR".to_string());
+        }
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join(" "))
+        }
+    }
+}
+
+struct LineTransition {
+    frames: u32,
+    to_eliminate: Vec<usize>,
+}
+
+impl LineTransition {
+    fn progress(&self) -> f32 {
+        self.frames as f32 / LINE_TRANSITION_FRAMES as f32
     }
 }
 
+fn check_lines(playfield: &mut Playfield, state: &mut State) {
+    let lt = match &mut state.line_transition {
+        Some(lt) => lt,
+        None => return,
+    };
+    lt.frames += 1;
+    if lt.frames < LINE_TRANSITION_FRAMES {
+        return;
+    }
+
+    for line in lt.to_eliminate.iter().rev() {
+        playfield.remove_row(*line);
+    }
+    state.line_transition = None;
+}
+
 struct Preview;
 
 struct BevrisPlugin;
 #[derive(Default)]
 struct State {
     event_reader: EventReader<KeyboardInput>,
-    timer: Timer,
-    fast_timer: Timer,
-    fast_generation: Option<Entity>,
+    key_presses: Vec<KeyCode>,
+    // all timing is counted in simulation ticks
+    frames_per_row: u32,
+    gravity_frames: u32,
+    fast_frames: u32,
+    das_frames: u32,
+    generation: usize,
+    fast_generation: Option<usize>,
     lines: usize,
+    line_transition: Option<LineTransition>,
+    // simulation ticks since the start of the game
+    ticks: u64,
+    finished: bool,
+    topped_out: bool,
+    // tick at which the next garbage row rises (survival mode)
+    next_garbage: u64,
+    // e.g. while the puzzle picker is shown
+    waiting: bool,
+    // the timers don't run and the board is hidden, see pause
+    paused: bool,
+    // the last successful move of the active piece was a rotation
+    rotated: bool,
+    last_clear: Option<LineClear>,
+    perfect_clears: usize,
+    combo: usize,
+    b2b: usize,
+    // total lines of garbage sent
+    attack: usize,
+    callout: Option<Callout>,
+    // every locked piece, for exporting the game as fumen
+    placements: Vec<fumen::Page>,
+    hold: Option<PieceType>,
+    // only one hold per piece
+    hold_used: bool,
+    // the piece was hard dropped and locks on the next update
+    hard_drop: bool,
+    // queued during the simulation ticks, see events::send_events_system
+    events: Vec<GameEvent>,
 }
 
 impl State {
+    fn level(&self) -> usize {
+        self.lines / 10
+    }
+
+    // continues or breaks the combo and back-to-back chains with the outcome of a lock
+    fn record_clear(&mut self, lines: usize, tspin: bool, perfect_clear: bool) -> LineClear {
+        let difficult = lines >= 4 || (tspin && lines > 0);
+        if lines == 0 {
+            self.combo = 0;
+        } else {
+            self.combo += 1;
+            // an easy clear breaks the chain, locking without clearing does not
+            self.b2b = if difficult { self.b2b + 1 } else { 0 };
+        }
+        let clear = LineClear {
+            lines,
+            tspin,
+            perfect_clear,
+            combo: self.combo,
+            b2b: if difficult { self.b2b } else { 0 },
+        };
+        self.last_clear = Some(clear);
+        clear
+    }
+
+    // the active piece is drawn into the field, except while cleared lines are shown (the next piece is placed
+    // afterwards) and after it could not be placed
+    fn piece_on_board(&self) -> bool {
+        self.line_transition.is_none() && !self.topped_out
+    }
+
     fn update_speed(&mut self) {
         let frames_per_row = [
             53, 49, 45, 41, 37, 33, 28, 22, 17, 11, 10, 9, 8, 7, 6, 6, 5, 5, 4, 4, 3,
         ];
-        let mut level = self.lines / 10;
+        let mut level = self.level();
         if level >= frames_per_row.len() {
             level = frames_per_row.len() - 1;
         }
-        self.timer.duration =
-            std::time::Duration::from_micros(16666 * frames_per_row[level]).as_secs_f32();
-        self.timer.reset();
-        println!("update: {:?} {}", self.timer, level);
+        self.frames_per_row = frames_per_row[level];
+        self.gravity_frames = 0;
+        println!("update: {} {}", self.frames_per_row, level);
     }
 }
 
+// the upcoming pieces, refilled with shuffled bags of all seven pieces
 #[derive(Default)]
 struct PieceBag {
-    bag: Vec<PieceType>,
-    preview: Option<PieceType>,
+    queue: VecDeque<PieceType>,
 }
 
 impl PieceBag {
-    fn next_int(&mut self) -> PieceType {
-        if self.bag.is_empty() {
-            self.bag = vec![
+    fn fill(&mut self, n: usize) {
+        while self.queue.len() < n {
+            let mut bag = vec![
                 PieceType::I,
                 PieceType::L,
                 PieceType::J,
@@ -315,24 +862,25 @@ impl PieceBag {
                 PieceType::T,
             ];
 
-            self.bag.shuffle(&mut rand::thread_rng());
+            bag.shuffle(&mut rand::thread_rng());
+            self.queue.extend(bag);
         }
-        self.bag.pop().unwrap()
     }
 
     fn next(&mut self) -> PieceType {
-        let ret = match self.preview {
-            None => self.next_int(),
-            Some(piece) => piece,
-        };
-        self.preview = Some(self.next_int());
-        ret
+        self.fill(1);
+        self.queue.pop_front().unwrap()
     }
-    fn peek_preview(&mut self) -> PieceType {
-        if self.preview.is_none() {
-            self.preview = Some(self.next_int());
-        }
-        self.preview.unwrap()
+
+    // replaces the bag with a fixed sequence of pieces. random bags follow once it is used up
+    fn set_queue(&mut self, queue: &[PieceType]) {
+        self.queue = queue.iter().cloned().collect();
+    }
+
+    // the next n pieces, without taking them
+    fn peek_queue(&mut self, n: usize) -> Vec<PieceType> {
+        self.fill(n);
+        self.queue.iter().take(n).cloned().collect()
     }
 }
 
@@ -342,14 +890,127 @@ impl Plugin for BevrisPlugin {
             // Adds a system that prints diagnostics to the console
             .add_plugin(PrintDiagnosticsPlugin::default())
             .add_resource(Playfield::new())
+            .add_plugin(timestep::FixedTimestepPlugin)
+            .add_plugin(mode::ModePlugin)
+            .add_plugin(puzzle::PuzzlePlugin)
+            .add_plugin(fumen::FumenPlugin)
+            .add_plugin(events::GameEventsPlugin)
+            .add_plugin(theme::ThemePlugin)
+            .add_plugin(layout::LayoutPlugin)
+            .add_plugin(bitmap_font::BitmapFontPlugin)
+            .add_plugin(sound::SoundPlugin)
+            .add_plugin(music::MusicPlugin)
+            .add_plugin(bindings::BindingsPlugin)
+            .add_plugin(pause::PausePlugin)
             // .add_system(modify_test.system())
-            .add_system(player_input_system.system())
-            .add_system(piece_update_system.system())
-            .add_system(check_lines_system.system())
-            // .add_system(check_lines_system.system())
-            // .add_plugin(field::SolidFieldPlugin)
-            .add_plugin(field::TexturedFieldPlugin)
+            .add_system(simulation_system.system())
+            .add_system(scoreboard_system.system())
+            .add_system(callout_system.system())
+            .add_plugin(field::FieldPlugin)
             // sentinel
             ;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bag_deals_each_piece_once_per_seven() {
+        let mut bag = PieceBag::default();
+        for _ in 0..10 {
+            let mut pieces = (0..7).map(|_| bag.next()).collect::<Vec<_>>();
+            pieces.sort_by_key(|t| *t as usize);
+            pieces.dedup();
+            assert_eq!(pieces.len(), 7);
+        }
+    }
+
+    #[test]
+    fn bag_peek_does_not_take() {
+        let mut bag = PieceBag::default();
+        let queue = bag.peek_queue(10);
+        assert_eq!(queue.len(), 10);
+        assert_eq!(bag.peek_queue(3), &queue[..3]);
+        assert_eq!((0..10).map(|_| bag.next()).collect::<Vec<_>>(), queue);
+    }
+
+    #[test]
+    fn bag_fixed_queue() {
+        let mut bag = PieceBag::default();
+        bag.peek_queue(5);
+        bag.set_queue(&[PieceType::T, PieceType::T, PieceType::I]);
+        assert_eq!(bag.next(), PieceType::T);
+        assert_eq!(bag.next(), PieceType::T);
+        assert_eq!(bag.next(), PieceType::I);
+        // random bags follow
+        let mut pieces = (0..7).map(|_| bag.next()).collect::<Vec<_>>();
+        pieces.sort_by_key(|t| *t as usize);
+        pieces.dedup();
+        assert_eq!(pieces.len(), 7);
+    }
+
+    fn score(
+        state: &mut State,
+        lines: usize,
+        tspin: bool,
+        perfect_clear: bool,
+        level: usize,
+    ) -> usize {
+        let mut scoreboard = Scoreboard::default();
+        let clear = state.record_clear(lines, tspin, perfect_clear);
+        scoreboard.add_clear(&clear, level);
+        scoreboard.score
+    }
+
+    #[test]
+    fn line_clear_points() {
+        let mut state = State::default();
+        assert_eq!(score(&mut state, 0, false, false, 0), 0);
+        assert_eq!(score(&mut state, 1, false, false, 0), 40);
+        let mut state = State::default();
+        assert_eq!(score(&mut state, 4, false, false, 2), 1200 * 3);
+        let mut state = State::default();
+        assert_eq!(score(&mut state, 2, true, false, 0), 1200);
+        // a t-spin without lines still scores
+        assert_eq!(score(&mut state, 0, true, false, 0), 400);
+        let mut state = State::default();
+        assert_eq!(score(&mut state, 4, false, true, 0), 1200 + 2000);
+    }
+
+    #[test]
+    fn combo() {
+        let mut state = State::default();
+        assert_eq!(score(&mut state, 1, false, false, 0), 40);
+        assert_eq!(score(&mut state, 1, false, false, 0), 40 + 50);
+        assert_eq!(score(&mut state, 2, false, false, 0), 100 + 100);
+        assert_eq!(state.combo, 3);
+        // locking without a clear ends the combo
+        score(&mut state, 0, false, false, 0);
+        assert_eq!(state.combo, 0);
+        assert_eq!(score(&mut state, 1, false, false, 0), 40);
+    }
+
+    #[test]
+    fn back_to_back() {
+        let mut state = State::default();
+        assert_eq!(score(&mut state, 4, false, false, 0), 1200);
+        // locking without a clear keeps the chain
+        score(&mut state, 0, false, false, 0);
+        assert_eq!(score(&mut state, 4, false, false, 0), 1800);
+        assert_eq!(state.b2b, 2);
+        assert_eq!(state.last_clear.unwrap().attack(), 4 + 1);
+        assert_eq!(
+            state.last_clear.unwrap().callout().as_deref(),
+            Some("B2B TETRIS")
+        );
+        // t-spins continue it
+        assert_eq!(score(&mut state, 1, true, false, 0), 1200 + 50);
+        assert_eq!(state.b2b, 3);
+        // an easy clear breaks it
+        score(&mut state, 1, false, false, 0);
+        assert_eq!(state.b2b, 0);
+        assert_eq!(state.last_clear.unwrap().b2b, 0);
+    }
+}
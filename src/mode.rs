@@ -0,0 +1,419 @@
+use super::{
+    field::Playfield,
+    puzzle::Puzzle,
+    timestep::{ticks_to_duration, TICKS_PER_SECOND},
+    Scoreboard, State,
+};
+use bevy::prelude::*;
+use std::time::Duration;
+
+// i.e. until level 15 is reached (levels start at 0, one every 10 lines)
+const MARATHON_LINES: usize = 150;
+const SPRINT_LINES: [usize; 3] = [20, 40, 100];
+// leave some room for spawning pieces
+const MAX_DIG_ROWS: usize = 16;
+const PERSONAL_BEST_FILE: &str = "bevris_pb.txt";
+// survival: a garbage row every 8 seconds at the start, one second less every 40 seconds, down to one per second
+const SURVIVAL_START_TICKS: u64 = 8 * TICKS_PER_SECOND as u64;
+const SURVIVAL_MIN_TICKS: u64 = TICKS_PER_SECOND as u64;
+const SURVIVAL_SPEEDUP: u64 = 40;
+
+#[derive(Debug, Clone)]
+pub enum GameMode {
+    // the classic game, ends after the given number of lines (or never)
+    Marathon { lines: Option<usize> },
+    Sprint { lines: usize },
+    // score attack until the time limit (in simulation ticks) runs out
+    Ultra { ticks: u64 },
+    // clear all pre-filled garbage rows as fast as possible
+    Dig { rows: usize, messiness: f32 },
+    // survive as long as possible while garbage keeps rising from the bottom
+    Survival { messiness: f32 },
+    // None while the puzzle picker is shown
+    Puzzle(Option<Puzzle>),
+}
+
+impl Default for GameMode {
+    fn default() -> Self {
+        GameMode::Marathon {
+            lines: Some(MARATHON_LINES),
+        }
+    }
+}
+
+impl GameMode {
+    // e.g. `bevris --mode sprint --lines 20`, `bevris --mode ultra --time 180`, `bevris --endless`
+    // `bevris --mode dig --rows 18 --messiness 0.3`, `bevris --mode puzzle` or `bevris --puzzle <file>`
+    pub fn from_args() -> Self {
+        let args = std::env::args().collect::<Vec<_>>();
+        let value_of = |name: &str| {
+            args.iter()
+                .position(|arg| arg == name)
+                .and_then(|i| args.get(i + 1))
+                .cloned()
+        };
+
+        let mut mode = value_of("--mode");
+        // a single puzzle implies the puzzle mode
+        if args.iter().any(|arg| arg == "--puzzle") {
+            if let Some(mode) = mode.as_deref().filter(|mode| *mode != "puzzle") {
+                println!(
+                    "--puzzle is only supported in puzzle mode, ignoring --mode {}",
+                    mode
+                );
+            }
+            mode = Some("puzzle".to_string());
+        }
+
+        match mode.as_deref() {
+            None | Some("marathon") => {
+                let lines = if args.iter().any(|arg| arg == "--endless") {
+                    None
+                } else {
+                    Some(
+                        value_of("--lines")
+                            .and_then(|lines| lines.parse().ok())
+                            .filter(|lines| *lines > 0)
+                            .unwrap_or(MARATHON_LINES),
+                    )
+                };
+                GameMode::Marathon { lines }
+            }
+            Some("sprint") => {
+                let lines = value_of("--lines")
+                    .and_then(|lines| lines.parse().ok())
+                    .unwrap_or(40);
+                if !SPRINT_LINES.contains(&lines) {
+                    println!("unsupported sprint length {}. using 40", lines);
+                    return GameMode::Sprint { lines: 40 };
+                }
+                GameMode::Sprint { lines }
+            }
+            Some("ultra") => {
+                let seconds = value_of("--time")
+                    .and_then(|seconds| seconds.parse::<u64>().ok())
+                    .filter(|seconds| *seconds > 0)
+                    .unwrap_or(120);
+                GameMode::Ultra {
+                    ticks: seconds * TICKS_PER_SECOND as u64,
+                }
+            }
+            Some("dig") => GameMode::Dig {
+                rows: value_of("--rows")
+                    .and_then(|rows| rows.parse().ok())
+                    .unwrap_or(10)
+                    .min(MAX_DIG_ROWS),
+                messiness: value_of("--messiness")
+                    .and_then(|messiness| messiness.parse().ok())
+                    .unwrap_or(1.0),
+            },
+            Some("survival") => GameMode::Survival {
+                messiness: value_of("--messiness")
+                    .and_then(|messiness| messiness.parse().ok())
+                    .unwrap_or(0.3),
+            },
+            Some("puzzle") => GameMode::Puzzle(None),
+            Some(mode) => {
+                println!("unknown mode: {}. using marathon", mode);
+                GameMode::default()
+            }
+        }
+    }
+
+    pub fn is_finished(&self, state: &State, playfield: &Playfield) -> bool {
+        match *self {
+            GameMode::Marathon { lines } => lines.map_or(false, |lines| state.lines >= lines),
+            GameMode::Sprint { lines } => state.lines >= lines,
+            GameMode::Ultra { ticks } => state.ticks >= ticks,
+            GameMode::Dig { .. } => playfield.garbage_rows() == 0,
+            GameMode::Survival { .. } => false,
+            GameMode::Puzzle(Some(ref puzzle)) => {
+                puzzle.is_solved(state) || puzzle.is_failed(state)
+            }
+            GameMode::Puzzle(None) => false,
+        }
+    }
+
+    // messiness of the garbage that should rise on this tick, if any
+    pub fn rising_garbage(&self, state: &mut State) -> Option<f32> {
+        match *self {
+            GameMode::Survival { messiness } if state.ticks >= state.next_garbage => {
+                state.next_garbage = state.ticks + survival_interval(state.ticks);
+                Some(messiness)
+            }
+            _ => None,
+        }
+    }
+
+    // key into the personal best file, None if the mode does not keep records
+    fn record_name(&self) -> Option<String> {
+        match *self {
+            GameMode::Marathon { lines: None } => Some("marathon".to_string()),
+            GameMode::Marathon { lines: Some(lines) } => Some(format!("marathon{}", lines)),
+            GameMode::Sprint { lines } => Some(format!("sprint{}", lines)),
+            GameMode::Ultra { ticks } => Some(format!("ultra{}", ticks / TICKS_PER_SECOND as u64)),
+            GameMode::Dig { rows, .. } => Some(format!("dig{}", rows)),
+            GameMode::Survival { .. } => Some("survival".to_string()),
+            GameMode::Puzzle(_) => None,
+        }
+    }
+}
+
+// ticks until the next garbage row rises, given the ticks since the start of the game
+fn survival_interval(ticks: u64) -> u64 {
+    SURVIVAL_START_TICKS
+        .saturating_sub(ticks / SURVIVAL_SPEEDUP)
+        .max(SURVIVAL_MIN_TICKS)
+}
+
+pub fn format_time(time: Duration) -> String {
+    let millis = time.as_millis();
+    format!(
+        "{}:{:02}.{:03}",
+        millis / 60_000,
+        (millis / 1000) % 60,
+        millis % 1000
+    )
+}
+
+// personal bests are stored as one `<record name> <value>` line per record
+pub struct PersonalBests {
+    records: Vec<(String, u64)>,
+}
+
+impl PersonalBests {
+    pub fn load() -> Self {
+        let records = std::fs::read_to_string(PERSONAL_BEST_FILE)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| {
+                let mut tokens = line.split_whitespace();
+                let name = tokens.next()?;
+                let value = tokens.next()?.parse().ok()?;
+                Some((name.to_string(), value))
+            })
+            .collect();
+        PersonalBests { records }
+    }
+
+    pub fn get(&self, name: &str) -> Option<u64> {
+        self.records
+            .iter()
+            .find(|(record_name, _)| record_name == name)
+            .map(|(_, value)| *value)
+    }
+
+    // returns true if value is a new personal best
+    pub fn submit(&mut self, name: &str, value: u64, lower_is_better: bool) -> bool {
+        let improved = match self.get(name) {
+            None => true,
+            Some(best) if lower_is_better => value < best,
+            Some(best) => value > best,
+        };
+        if improved {
+            self.records.retain(|(record_name, _)| record_name != name);
+            self.records.push((name.to_string(), value));
+        }
+        improved
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let content = self
+            .records
+            .iter()
+            .map(|(name, value)| format!("{} {}\n", name, value))
+            .collect::<String>();
+        std::fs::write(PERSONAL_BEST_FILE, content)
+    }
+}
+
+pub struct ModeText;
+
+fn setup_mode_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(TextComponents {
+            text: Text {
+                font: asset_server
+                    .load("assets/fonts/FiraMono-Medium.ttf")
+                    .unwrap(),
+                value: String::new(),
+                style: TextStyle {
+                    color: Color::rgb(0.2, 0.2, 0.8),
+                    font_size: 30.0,
+                },
+            },
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(50.0),
+                    left: Val::Px(5.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with(ModeText);
+}
+
+fn setup_mode(mode: Res<GameMode>, mut state: ResMut<State>, mut playfield: ResMut<Playfield>) {
+    match *mode {
+        GameMode::Dig { rows, messiness } => {
+            playfield.push_garbage(rows, messiness, &mut rand::thread_rng());
+        }
+        GameMode::Survival { .. } => state.next_garbage = survival_interval(0),
+        GameMode::Puzzle(None) => state.waiting = true,
+        _ => (),
+    }
+}
+
+fn mode_text_system(
+    mode: Res<GameMode>,
+    state: Res<State>,
+    playfield: Res<Playfield>,
+    mut query: Query<(&ModeText, &mut Text)>,
+) {
+    let value = match *mode {
+        GameMode::Marathon { .. } => format!(
+            "Level {} Lines {} {}",
+            state.level(),
+            state.lines,
+            format_time(ticks_to_duration(state.ticks))
+        ),
+        GameMode::Sprint { lines } => format!(
+            "{}/{} {}",
+            state.lines.min(lines),
+            lines,
+            format_time(ticks_to_duration(state.ticks))
+        ),
+        GameMode::Ultra { ticks } => {
+            format_time(ticks_to_duration(ticks.saturating_sub(state.ticks)))
+        }
+        GameMode::Dig { .. } => format!(
+            "Garbage {} {}",
+            playfield.garbage_rows(),
+            format_time(ticks_to_duration(state.ticks))
+        ),
+        GameMode::Survival { .. } => format!(
+            "Lines {} {}",
+            state.lines,
+            format_time(ticks_to_duration(state.ticks))
+        ),
+        GameMode::Puzzle(Some(ref puzzle)) => format!(
+            "{} Pieces {}",
+            puzzle.goal.describe(),
+            puzzle.pieces_left(&state)
+        ),
+        GameMode::Puzzle(None) => "Select a puzzle".to_string(),
+    };
+    for (_, mut text) in &mut query.iter() {
+        text.value = value.clone();
+    }
+}
+
+struct ResultsText;
+
+// shows the results screen and records personal bests once the game has finished
+fn game_result_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mode: Res<GameMode>,
+    state: Res<State>,
+    scoreboard: Res<Scoreboard>,
+    mut query: Query<&ResultsText>,
+) {
+    if !state.finished || query.iter().iter().len() != 0 {
+        return;
+    }
+
+    let time = ticks_to_duration(state.ticks);
+    let mut results = format!(
+        "{}\nScore: {}\nLines: {}\nLevel: {}\nPerfect clears: {}\nAttack: {}\nTime: {}",
+        match *mode {
+            GameMode::Puzzle(Some(ref puzzle)) if puzzle.is_solved(&state) => "SOLVED",
+            GameMode::Puzzle(_) => "FAILED",
+            _ if state.topped_out => "GAME OVER",
+            _ => "FINISHED",
+        },
+        scoreboard.score,
+        state.lines,
+        state.level(),
+        state.perfect_clears,
+        state.attack,
+        format_time(time)
+    );
+
+    let record = match *mode {
+        // races only count if the goal was reached
+        GameMode::Sprint { .. } | GameMode::Dig { .. } if state.topped_out => None,
+        GameMode::Sprint { .. } | GameMode::Dig { .. } => Some((time.as_millis() as u64, true)),
+        GameMode::Marathon { .. } | GameMode::Ultra { .. } => {
+            Some((scoreboard.score as u64, false))
+        }
+        GameMode::Survival { .. } => Some((time.as_millis() as u64, false)),
+        GameMode::Puzzle(_) => None,
+    };
+    if let (Some(record_name), Some((value, lower_is_better))) = (mode.record_name(), record) {
+        let mut personal_bests = PersonalBests::load();
+        if personal_bests.submit(&record_name, value, lower_is_better) {
+            results.push_str("\nNEW PERSONAL BEST");
+            if let Err(err) = personal_bests.save() {
+                println!("failed to save personal best: {}", err);
+            }
+        }
+    }
+    println!("{}", results);
+
+    commands
+        .spawn(TextComponents {
+            text: Text {
+                font: asset_server.load("assets/fonts/FiraSans-Bold.ttf").unwrap(),
+                value: results,
+                style: TextStyle {
+                    color: Color::rgb(0.8, 0.2, 0.2),
+                    font_size: 40.0,
+                },
+            },
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(150.0),
+                    left: Val::Px(5.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with(ResultsText);
+}
+
+pub struct ModePlugin;
+
+impl Plugin for ModePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_resource(GameMode::from_args())
+            .add_startup_system(setup_mode.system())
+            .add_startup_system(setup_mode_text.system())
+            .add_system(mode_text_system.system())
+            .add_system(game_result_system.system());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn survival_gets_faster() {
+        let minute = 60 * TICKS_PER_SECOND as u64;
+        assert_eq!(survival_interval(0), SURVIVAL_START_TICKS);
+        let intervals = (0..10)
+            .map(|minutes| survival_interval(minutes * minute))
+            .collect::<Vec<_>>();
+        assert!(intervals.windows(2).all(|pair| pair[0] >= pair[1]));
+        assert!(intervals[1] < intervals[0]);
+        assert_eq!(survival_interval(60 * minute), SURVIVAL_MIN_TICKS);
+        assert!(survival_interval(u64::MAX) > 0);
+    }
+}
@@ -0,0 +1,254 @@
+use super::{
+    field::{Cell, Playfield},
+    mode::GameMode,
+    Piece, PieceType, Pieces, State,
+};
+use bevy::prelude::*;
+use rodio::{Device, Sample, Sink, Source};
+use std::{
+    fs::File,
+    io::BufReader,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+const FADE_SECONDS: f32 = 2.0;
+// the track changes every few levels
+const LEVELS_PER_TRACK: usize = 5;
+// stack height (in rows) at which the danger music starts / stops, the gap avoids flapping
+const DANGER_ROWS: usize = 15;
+const SAFE_ROWS: usize = 12;
+const DANGER_SPEED: f32 = 1.15;
+
+fn playlist(mode: &GameMode) -> &'static [&'static str] {
+    match *mode {
+        GameMode::Marathon { .. } => &[
+            "assets/music/a.mp3",
+            "assets/music/b.mp3",
+            "assets/music/c.mp3",
+        ],
+        GameMode::Sprint { .. } | GameMode::Ultra { .. } => &["assets/music/race.mp3"],
+        GameMode::Dig { .. } | GameMode::Survival { .. } => {
+            &["assets/music/b.mp3", "assets/music/race.mp3"]
+        }
+        GameMode::Puzzle(_) => &["assets/music/puzzle.mp3"],
+    }
+}
+
+// Like rodio's Speed, but the factor can be changed while the track is playing. As with Speed, only the reported
+// sample rate changes, the output picks it up at the next frame of the decoder.
+struct Tempo<I> {
+    input: I,
+    // f32 bits, shared with Playing
+    factor: Arc<AtomicU32>,
+}
+
+impl<I> Iterator for Tempo<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        self.input.next()
+    }
+}
+
+impl<I> Source for Tempo<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        let factor = f32::from_bits(self.factor.load(Ordering::Relaxed));
+        (self.input.sample_rate() as f32 * factor) as u32
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+struct Playing {
+    // the track is identified by its file, the speed changes while it is playing
+    path: &'static str,
+    speed: Arc<AtomicU32>,
+    // None without audio device or if the file could not be played
+    sink: Option<Sink>,
+    // 0.0 ..= 1.0, multiplied with the volume
+    fade: f32,
+}
+
+pub struct Music {
+    // None in headless environments, the music is tracked but not played
+    device: Option<Device>,
+    pub volume: f32,
+    pub muted: bool,
+    // selects another track of the playlist (N)
+    skip: usize,
+    danger: bool,
+    current: Option<Playing>,
+    fading_out: Vec<Playing>,
+}
+
+impl Music {
+    // `--no-music` disables the music entirely
+    fn from_args() -> Self {
+        let device = if std::env::args().any(|arg| arg == "--no-music") {
+            None
+        } else {
+            rodio::default_output_device()
+        };
+        if device.is_none() {
+            println!("no audio device, music disabled");
+        }
+        Music {
+            device,
+            volume: 0.5,
+            muted: false,
+            skip: 0,
+            danger: false,
+            current: None,
+            fading_out: Vec::new(),
+        }
+    }
+
+    fn start(&self, path: &'static str, speed: f32) -> Playing {
+        let speed = Arc::new(AtomicU32::new(speed.to_bits()));
+        let sink = self.device.as_ref().and_then(|device| {
+            let source = File::open(path)
+                .map_err(|err| err.to_string())
+                .and_then(|file| {
+                    rodio::Decoder::new(BufReader::new(file)).map_err(|err| err.to_string())
+                });
+            match source {
+                Ok(source) => {
+                    let sink = Sink::new(device);
+                    sink.set_volume(0.0);
+                    sink.append(Tempo {
+                        input: source.repeat_infinite(),
+                        factor: speed.clone(),
+                    });
+                    Some(sink)
+                }
+                Err(err) => {
+                    println!("failed to play music {}: {}", path, err);
+                    None
+                }
+            }
+        });
+        Playing {
+            path,
+            speed,
+            sink,
+            fade: 0.0,
+        }
+    }
+
+    // crossfades to the given track (file and speed), None fades out. Only the tempo changes if the track is
+    // already playing
+    fn switch(&mut self, track: Option<(&'static str, f32)>) {
+        if let (Some(playing), Some((path, speed))) = (&self.current, track) {
+            if playing.path == path {
+                playing.speed.store(speed.to_bits(), Ordering::Relaxed);
+                return;
+            }
+        }
+        if let Some(playing) = self.current.take() {
+            self.fading_out.push(playing);
+        }
+        self.current = track.map(|(path, speed)| self.start(path, speed));
+    }
+}
+
+// height of the stack without the active piece
+fn stack_height(playfield: &Playfield, pieces: &Pieces, t: &PieceType, p: &Piece) -> usize {
+    let mut field = *playfield.board();
+    for (x, y, _) in pieces.get_solid(t, p).iter() {
+        field[*y as usize][*x as usize] = Cell::EMPTY;
+    }
+    field
+        .iter()
+        .rposition(|line| line.iter().any(|c| c.is_solid()))
+        .map_or(0, |y| y + 1)
+}
+
+fn music_system(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mode: Res<GameMode>,
+    state: Res<State>,
+    playfield: Res<Playfield>,
+    pieces: Res<Pieces>,
+    mut music: ResMut<Music>,
+    mut query: Query<(&PieceType, &Piece)>,
+) {
+    if keyboard_input.just_pressed(KeyCode::M) {
+        music.muted = !music.muted;
+    }
+    if keyboard_input.just_pressed(KeyCode::N) {
+        music.skip += 1;
+    }
+
+    for (t, p) in &mut query.iter() {
+        let height = stack_height(&playfield, &pieces, &t, &p);
+        if height >= DANGER_ROWS {
+            music.danger = true;
+        } else if height <= SAFE_ROWS {
+            music.danger = false;
+        }
+    }
+
+    let track = if state.waiting || state.finished {
+        None
+    } else {
+        let playlist = playlist(&mode);
+        let level = state.level();
+        // a bit faster every level, up to level 10
+        let mut speed = 1.0 + 0.02 * level.min(10) as f32;
+        if music.danger {
+            speed *= DANGER_SPEED;
+        }
+        Some((
+            playlist[(level / LEVELS_PER_TRACK + music.skip) % playlist.len()],
+            speed,
+        ))
+    };
+    music.switch(track);
+
+    let step = time.delta_seconds / FADE_SECONDS;
+    let volume = if music.muted { 0.0 } else { music.volume };
+    if let Some(playing) = &mut music.current {
+        playing.fade = (playing.fade + step).min(1.0);
+    }
+    for playing in music.fading_out.iter_mut() {
+        playing.fade = (playing.fade - step).max(0.0);
+    }
+    music.fading_out.retain(|playing| playing.fade > 0.0);
+    for playing in music.current.iter().chain(music.fading_out.iter()) {
+        if let Some(sink) = &playing.sink {
+            sink.set_volume(volume * playing.fade);
+        }
+    }
+}
+
+pub struct MusicPlugin;
+
+impl Plugin for MusicPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_resource(Music::from_args())
+            .add_system(music_system.system());
+    }
+}
@@ -0,0 +1,45 @@
+use super::{
+    bindings::{Action, Bindings, Rebinding},
+    Playfield, State,
+};
+use bevy::{prelude::*, window::WindowFocused};
+
+// The pause action (P or Escape by default) pauses and resumes the game. Losing the window focus pauses it as
+// well, resuming is always manual. Everything is counted in simulation ticks, so skipping the simulation freezes
+// all timers (gravity, das, line transitions and the time of the modes).
+fn pause_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    bindings: Res<Bindings>,
+    rebinding: Res<Rebinding>,
+    focus_events: Res<Events<WindowFocused>>,
+    mut focus_reader: Local<EventReader<WindowFocused>>,
+    mut state: ResMut<State>,
+    mut playfield: ResMut<Playfield>,
+) {
+    let lost_focus = focus_reader.iter(&focus_events).any(|event| !event.focused);
+    // the keys are rebound while the rebinding screen is open
+    let toggle = !rebinding.open && bindings.just_pressed(&keyboard_input, Action::Pause);
+    let paused = if state.finished || state.waiting {
+        false
+    } else if lost_focus {
+        true
+    } else if toggle {
+        !state.paused
+    } else {
+        state.paused
+    };
+    if paused != state.paused {
+        state.paused = paused;
+        // hide or show the board
+        playfield.mark_all_dirty();
+        println!("{}", if paused { "paused" } else { "resumed" });
+    }
+}
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(pause_system.system());
+    }
+}
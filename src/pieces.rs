@@ -1,3 +1,4 @@
+use super::field::Cell;
 use arrayvec::ArrayVec;
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum PieceType {
@@ -10,6 +11,21 @@ pub enum PieceType {
     O,
 }
 
+impl PieceType {
+    pub fn from_char(c: char) -> Option<Self> {
+        match c.to_ascii_uppercase() {
+            'I' => Some(PieceType::I),
+            'L' => Some(PieceType::L),
+            'J' => Some(PieceType::J),
+            'S' => Some(PieceType::S),
+            'Z' => Some(PieceType::Z),
+            'T' => Some(PieceType::T),
+            'O' => Some(PieceType::O),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Piece {
     pub x: i32,
@@ -18,16 +34,17 @@ pub struct Piece {
 }
 
 pub struct Pieces {
-    i: Vec<[(i32, i32, i32); 4]>,
-    l: Vec<[(i32, i32, i32); 4]>,
-    j: Vec<[(i32, i32, i32); 4]>,
-    s: Vec<[(i32, i32, i32); 4]>,
-    z: Vec<[(i32, i32, i32); 4]>,
-    o: Vec<[(i32, i32, i32); 4]>,
-    t: Vec<[(i32, i32, i32); 4]>,
+    i: Vec<[(i32, i32, Cell); 4]>,
+    l: Vec<[(i32, i32, Cell); 4]>,
+    j: Vec<[(i32, i32, Cell); 4]>,
+    s: Vec<[(i32, i32, Cell); 4]>,
+    z: Vec<[(i32, i32, Cell); 4]>,
+    o: Vec<[(i32, i32, Cell); 4]>,
+    t: Vec<[(i32, i32, Cell); 4]>,
 }
 
-fn parse_piece(i: &str) -> Vec<[(i32, i32, i32); 4]> {
+// the hex digits are cell codes, see Cell::from_code
+fn parse_piece(i: &str) -> Vec<[(i32, i32, Cell); 4]> {
     let lines = i.lines().collect::<Vec<_>>();
     // let out: Vec<Vec<(i32, i32)>>
     let out = lines
@@ -38,16 +55,18 @@ fn parse_piece(i: &str) -> Vec<[(i32, i32, i32); 4]> {
                 .enumerate()
                 .map(|(y, line)| {
                     line.chars().enumerate().map(move |(x, c)| match c {
-                        '0'..='9' | 'a'..='f' => {
-                            Some((x as i32, 3 - y as i32, c.to_digit(16).unwrap() as i32))
-                        }
-                        'o' => Some((x as i32, 3 - y as i32, 1)),
+                        '0'..='9' | 'a'..='f' => Some((
+                            x as i32,
+                            3 - y as i32,
+                            Cell::from_code(c.to_digit(16).unwrap() as u8).unwrap(),
+                        )),
+                        'o' => Some((x as i32, 3 - y as i32, Cell::GARBAGE)),
                         _ => None,
                     })
                 })
                 .flatten()
                 .filter_map(|x| x)
-                .collect::<ArrayVec<[(i32, i32, i32); 4]>>()
+                .collect::<ArrayVec<[(i32, i32, Cell); 4]>>()
         })
         .map(|x| x.into_inner().unwrap())
         .collect::<Vec<_>>();
@@ -149,7 +168,7 @@ impl Pieces {
         }
     }
 
-    pub fn get_solid_base(&self, t: &PieceType) -> &Vec<[(i32, i32, i32); 4]> {
+    pub fn get_solid_base(&self, t: &PieceType) -> &Vec<[(i32, i32, Cell); 4]> {
         match *t {
             PieceType::I => &self.i,
             PieceType::L => &self.l,
@@ -161,7 +180,7 @@ impl Pieces {
         }
     }
 
-    pub fn get_solid(&self, t: &PieceType, p: &Piece) -> [(i32, i32, i32); 4] {
+    pub fn get_solid(&self, t: &PieceType, p: &Piece) -> [(i32, i32, Cell); 4] {
         let base = self.get_solid_base(t);
         let trans: ArrayVec<[_; 4]> = base[p.rot as usize % base.len()]
             .iter()
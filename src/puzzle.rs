@@ -0,0 +1,429 @@
+use super::{
+    field::{parse_board, Board, Playfield},
+    fumen,
+    mode::GameMode,
+    place_piece, Piece, PieceBag, PieceType, Pieces, State,
+};
+use bevy::prelude::*;
+use std::path::Path;
+
+const PUZZLE_DIR: &str = "assets/puzzles";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Goal {
+    Lines(usize),
+    PerfectClear,
+    // t-spin clearing the given number of lines
+    TSpin(usize),
+}
+
+impl Goal {
+    fn parse(i: &str) -> Result<Self, String> {
+        let tokens = i.split_whitespace().collect::<Vec<_>>();
+        match tokens[..] {
+            ["lines", lines] => lines
+                .parse()
+                .map(Goal::Lines)
+                .map_err(|_| format!("invalid line count: {}", lines)),
+            ["perfect-clear"] => Ok(Goal::PerfectClear),
+            ["tspin-single"] => Ok(Goal::TSpin(1)),
+            ["tspin-double"] => Ok(Goal::TSpin(2)),
+            ["tspin-triple"] => Ok(Goal::TSpin(3)),
+            _ => Err(format!("unknown goal: {}", i)),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match *self {
+            Goal::Lines(lines) => format!("Clear {} lines", lines),
+            Goal::PerfectClear => "Perfect clear".to_string(),
+            Goal::TSpin(1) => "T-spin single".to_string(),
+            Goal::TSpin(2) => "T-spin double".to_string(),
+            Goal::TSpin(_) => "T-spin triple".to_string(),
+        }
+    }
+
+    fn is_reached(&self, state: &State) -> bool {
+        match *self {
+            Goal::Lines(lines) => state.lines >= lines,
+            Goal::PerfectClear => state.last_clear.map_or(false, |clear| clear.perfect_clear),
+            Goal::TSpin(lines) => state
+                .last_clear
+                .map_or(false, |clear| clear.tspin && clear.lines == lines),
+        }
+    }
+}
+
+// Puzzle files consist of a `key: value` header followed by the board (or a `fumen: <data>` line instead), e.g.
+//
+// name: T-spin double
+// goal: tspin-double
+// queue: T
+// board:
+// xxxxxxx..x
+// xxxxxxx.xx
+#[derive(Debug, Clone)]
+pub struct Puzzle {
+    pub name: String,
+    pub board: Board,
+    pub queue: Vec<PieceType>,
+    pub goal: Goal,
+}
+
+impl Puzzle {
+    pub fn parse(i: &str) -> Result<Self, String> {
+        let mut name = String::new();
+        let mut queue = Vec::new();
+        let mut goal = None;
+        let mut board = None;
+        let mut lines = i.lines();
+        for line in &mut lines {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line == "board:" {
+                break;
+            }
+            let mut kv = line.splitn(2, ':');
+            let (key, value) = match (kv.next(), kv.next()) {
+                (Some(key), Some(value)) => (key.trim(), value.trim()),
+                _ => return Err(format!("expected 'key: value': {}", line)),
+            };
+            match key {
+                "name" => name = value.to_string(),
+                "goal" => goal = Some(Goal::parse(value)?),
+                "queue" => {
+                    queue = value
+                        .chars()
+                        .filter(|c| !c.is_whitespace())
+                        .map(|c| {
+                            PieceType::from_char(c).ok_or_else(|| format!("unknown piece: {}", c))
+                        })
+                        .collect::<Result<_, _>>()?
+                }
+                // the board of the first page. the pieces of the pages are the queue, unless given explicitly
+                "fumen" => {
+                    let pages = fumen::decode(value, &Pieces::default())?;
+                    let first = pages.first().ok_or("empty fumen")?;
+                    board = Some(first.field);
+                    if queue.is_empty() {
+                        queue = pages
+                            .iter()
+                            .filter_map(|page| page.piece.as_ref().map(|(t, _)| *t))
+                            .collect();
+                    }
+                }
+                _ => return Err(format!("unknown key: {}", key)),
+            }
+        }
+
+        let board = match board {
+            Some(board) => board,
+            None => parse_board(
+                &lines
+                    .filter(|line| !line.trim().is_empty())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            )?,
+        };
+        if queue.is_empty() {
+            return Err("empty queue".to_string());
+        }
+        Ok(Puzzle {
+            name,
+            board,
+            queue,
+            goal: goal.ok_or("missing goal")?,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let mut puzzle = Puzzle::parse(&content)?;
+        if puzzle.name.is_empty() {
+            puzzle.name = path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+        }
+        Ok(puzzle)
+    }
+
+    pub fn is_solved(&self, state: &State) -> bool {
+        self.goal.is_reached(state)
+    }
+
+    // pieces of the queue that are not locked yet, the held one included
+    pub fn pieces_left(&self, state: &State) -> usize {
+        self.queue.len().saturating_sub(state.placements.len())
+    }
+
+    // all pieces of the queue have been locked without reaching the goal
+    pub fn is_failed(&self, state: &State) -> bool {
+        !self.is_solved(state) && self.pieces_left(state) == 0
+    }
+}
+
+pub struct PuzzlePicker {
+    puzzles: Vec<Puzzle>,
+    selected: usize,
+    // start right away if a single puzzle was given on the command line
+    autostart: bool,
+}
+
+impl PuzzlePicker {
+    // `bevris --mode puzzle` lists the puzzles in assets/puzzles, `--puzzle <file>` plays a single one
+    fn from_args() -> Self {
+        let args = std::env::args().collect::<Vec<_>>();
+        if let Some(path) = args
+            .iter()
+            .position(|arg| arg == "--puzzle")
+            .and_then(|i| args.get(i + 1))
+        {
+            match Puzzle::load(Path::new(path)) {
+                Ok(puzzle) => {
+                    return PuzzlePicker {
+                        puzzles: vec![puzzle],
+                        selected: 0,
+                        autostart: true,
+                    }
+                }
+                Err(err) => println!("failed to load puzzle {}: {}", path, err),
+            }
+        }
+
+        let mut paths = std::fs::read_dir(PUZZLE_DIR)
+            .map(|dir| {
+                dir.filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        paths.sort();
+        let puzzles = paths
+            .iter()
+            .filter_map(|path| match Puzzle::load(path) {
+                Ok(puzzle) => Some(puzzle),
+                Err(err) => {
+                    println!("failed to load puzzle {:?}: {}", path, err);
+                    None
+                }
+            })
+            .collect();
+        PuzzlePicker {
+            puzzles,
+            selected: 0,
+            autostart: false,
+        }
+    }
+}
+
+struct PickerText;
+
+fn setup_puzzle_picker(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(TextComponents {
+            text: Text {
+                font: asset_server
+                    .load("assets/fonts/FiraMono-Medium.ttf")
+                    .unwrap(),
+                value: String::new(),
+                style: TextStyle {
+                    color: Color::rgb(0.2, 0.2, 0.8),
+                    font_size: 30.0,
+                },
+            },
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(100.0),
+                    left: Val::Px(5.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with(PickerText);
+}
+
+fn puzzle_picker_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    pieces: Res<Pieces>,
+    mut picker: ResMut<PuzzlePicker>,
+    mut mode: ResMut<GameMode>,
+    mut state: ResMut<State>,
+    mut playfield: ResMut<Playfield>,
+    mut piece_bag: ResMut<PieceBag>,
+    mut query: Query<(&mut PieceType, &mut Piece)>,
+    mut text_query: Query<(&PickerText, &mut Text)>,
+) {
+    match *mode {
+        GameMode::Puzzle(None) => (),
+        _ => return,
+    }
+
+    if picker.puzzles.is_empty() {
+        for (_, mut text) in &mut text_query.iter() {
+            text.value = format!("no puzzles found in {}", PUZZLE_DIR);
+        }
+        return;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Up) && picker.selected > 0 {
+        picker.selected -= 1;
+    }
+    if keyboard_input.just_pressed(KeyCode::Down) && picker.selected + 1 < picker.puzzles.len() {
+        picker.selected += 1;
+    }
+
+    if !picker.autostart && !keyboard_input.just_pressed(KeyCode::Return) {
+        let list = picker
+            .puzzles
+            .iter()
+            .enumerate()
+            .map(|(i, puzzle)| {
+                let cursor = if i == picker.selected { ">" } else { " " };
+                format!("{} {}: {}", cursor, puzzle.name, puzzle.goal.describe())
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        for (_, mut text) in &mut text_query.iter() {
+            text.value = list.clone();
+        }
+        return;
+    }
+
+    let puzzle = picker.puzzles[picker.selected].clone();
+    playfield.set_board(puzzle.board);
+    piece_bag.set_queue(&puzzle.queue);
+    for (mut t, mut p) in &mut query.iter() {
+        *t = piece_bag.next();
+        *p = Piece {
+            x: 3,
+            y: 16,
+            rot: 0,
+        };
+        place_piece(&mut playfield, &mut state, &pieces, &t, &p);
+    }
+    for (_, mut text) in &mut text_query.iter() {
+        text.value = String::new();
+    }
+    state.waiting = false;
+    *mode = GameMode::Puzzle(Some(puzzle));
+}
+
+pub struct PuzzlePlugin;
+
+impl Plugin for PuzzlePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_resource(PuzzlePicker::from_args())
+            .add_startup_system(setup_puzzle_picker.system())
+            .add_system(puzzle_picker_system.system());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hold;
+
+    #[test]
+    fn parse_board_puzzle() {
+        let puzzle = Puzzle::parse(
+            "name: T-spin double\n\
+             goal: tspin-double\n\
+             queue: T I\n\
+             board:\n\
+             xxxxxxx..x\n\
+             xxxxxxx.xx\n",
+        )
+        .unwrap();
+        assert_eq!(puzzle.name, "T-spin double");
+        assert_eq!(puzzle.goal, Goal::TSpin(2));
+        assert_eq!(puzzle.queue, vec![PieceType::T, PieceType::I]);
+        assert!(puzzle.board == parse_board("xxxxxxx..x\nxxxxxxx.xx").unwrap());
+    }
+
+    #[test]
+    fn parse_fumen_puzzle() {
+        // a garbage row with a T on top, the queue is taken from the pages
+        let puzzle = Puzzle::parse("goal: lines 1\nfumen: v115@bhJ8JeVLJ\n").unwrap();
+        assert_eq!(puzzle.goal, Goal::Lines(1));
+        assert_eq!(puzzle.queue, vec![PieceType::T]);
+        assert!(puzzle.board == parse_board("xxxxxxxxxx").unwrap());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Puzzle::parse("queue: T\nboard:\nxxxxxxxxx.\n").is_err());
+        assert!(Puzzle::parse("goal: lines 1\nboard:\nxxxxxxxxx.\n").is_err());
+        assert!(Puzzle::parse("goal: lines 1\nqueue: TX\nboard:\n").is_err());
+        assert!(Puzzle::parse("goal: lines one\nqueue: T\nboard:\n").is_err());
+        assert!(Puzzle::parse("goal: lines 1\nqueue: T\ncolor: red\nboard:\n").is_err());
+        assert!(Puzzle::parse("goal: lines 1\nqueue: T\nboard:\nxxxxx\n").is_err());
+    }
+
+    #[test]
+    fn failed_after_the_held_piece_is_locked() {
+        let puzzle = Puzzle::parse("goal: lines 1\nqueue: T I\nboard:\nxxxxxxxxx.\n").unwrap();
+        let playfield = Playfield::new();
+        let pieces = Pieces::default();
+        let mut state = State::default();
+        let mut piece_bag = PieceBag::default();
+        piece_bag.set_queue(&puzzle.queue);
+        let mut t = piece_bag.next();
+        let mut p = Piece {
+            x: 3,
+            y: 16,
+            rot: 0,
+        };
+        let lock = |state: &mut State, t: PieceType, p: &Piece| {
+            state.placements.push(fumen::Page {
+                field: puzzle.board,
+                piece: Some((t, p.clone())),
+                lock: true,
+            });
+            state.hold_used = false;
+        };
+
+        // hold the T, lock the I
+        hold(
+            &playfield,
+            &mut state,
+            &mut piece_bag,
+            &pieces,
+            &mut t,
+            &mut p,
+        );
+        assert_eq!(t, PieceType::I);
+        lock(&mut state, t, &p);
+        assert_eq!(puzzle.pieces_left(&state), 1);
+        assert!(!puzzle.is_failed(&state));
+
+        // the T is still in the hold box
+        hold(
+            &playfield,
+            &mut state,
+            &mut piece_bag,
+            &pieces,
+            &mut t,
+            &mut p,
+        );
+        assert_eq!(t, PieceType::T);
+        lock(&mut state, t, &p);
+        assert_eq!(puzzle.pieces_left(&state), 0);
+        assert!(puzzle.is_failed(&state));
+    }
+
+    #[test]
+    fn shipped_puzzles() {
+        for entry in std::fs::read_dir(PUZZLE_DIR).unwrap() {
+            let path = entry.unwrap().path();
+            let puzzle = Puzzle::load(&path).unwrap();
+            assert!(!puzzle.name.is_empty());
+        }
+    }
+}
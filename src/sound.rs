@@ -0,0 +1,343 @@
+use super::{
+    events::{
+        GameOver, LevelUp, LinesCleared, PieceHeld, PieceLocked, PieceMoved, PieceRotated, Spin,
+    },
+    theme::Theme,
+};
+use bevy::prelude::*;
+use rodio::{Device, Sink};
+use std::{collections::HashMap, io::Cursor, sync::Arc};
+
+const VOLUME_STEP: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Effect {
+    Move,
+    Rotate,
+    Hold,
+    Lock,
+    // number of lines
+    LineClear(usize),
+    TSpin,
+    LevelUp,
+    GameOver,
+}
+
+impl Effect {
+    // file name (without extension) in the sound directory of the theme
+    pub fn name(&self) -> String {
+        match *self {
+            Effect::Move => "move".to_string(),
+            Effect::Rotate => "rotate".to_string(),
+            Effect::Hold => "hold".to_string(),
+            Effect::Lock => "lock".to_string(),
+            Effect::LineClear(lines) => format!("line_clear_{}", lines.min(4)),
+            Effect::TSpin => "tspin".to_string(),
+            Effect::LevelUp => "level_up".to_string(),
+            Effect::GameOver => "game_over".to_string(),
+        }
+    }
+}
+
+pub enum Backend {
+    // plays through rodio, like the music (bevy's AudioOutput has no volume)
+    Audio(Device),
+    // only records the effects (and volume) that would have been played in the last frame, i.e. works without an
+    // audio device
+    Null(Vec<(Effect, f32)>),
+}
+
+pub struct Sound {
+    pub backend: Backend,
+    // 0.0 (muted) ..= 1.0
+    pub volume: f32,
+    // effects triggered during this frame
+    pending: Vec<Effect>,
+    // contents of the sound files, None if the file could not be read, so the error is reported only once and
+    // missing effects are skipped
+    files: HashMap<String, Option<Arc<[u8]>>>,
+}
+
+impl Sound {
+    // `bevris --volume 0.5`, `--no-sound` disables audio output entirely
+    pub fn from_args() -> Self {
+        let args = std::env::args().collect::<Vec<_>>();
+        let volume = args
+            .iter()
+            .position(|arg| arg == "--volume")
+            .and_then(|i| args.get(i + 1))
+            .and_then(|volume| volume.parse::<f32>().ok())
+            .unwrap_or(1.0)
+            .max(0.0)
+            .min(1.0);
+        let device = if args.iter().any(|arg| arg == "--no-sound") {
+            None
+        } else {
+            rodio::default_output_device()
+        };
+        let backend = match device {
+            Some(device) => Backend::Audio(device),
+            None => Backend::Null(Vec::new()),
+        };
+        Sound {
+            backend,
+            volume,
+            pending: Vec::new(),
+            files: HashMap::new(),
+        }
+    }
+
+    pub fn play(&mut self, effect: Effect) {
+        self.pending.push(effect);
+    }
+}
+
+#[derive(Default)]
+struct SoundEventReaders {
+    moved: EventReader<PieceMoved>,
+    rotated: EventReader<PieceRotated>,
+    held: EventReader<PieceHeld>,
+    locked: EventReader<PieceLocked>,
+    cleared: EventReader<LinesCleared>,
+    level_up: EventReader<LevelUp>,
+    game_over: EventReader<GameOver>,
+}
+
+// maps the gameplay events to sound effects
+fn sound_event_system(
+    mut readers: Local<SoundEventReaders>,
+    mut sound: ResMut<Sound>,
+    moved: Res<Events<PieceMoved>>,
+    rotated: Res<Events<PieceRotated>>,
+    held: Res<Events<PieceHeld>>,
+    locked: Res<Events<PieceLocked>>,
+    cleared: Res<Events<LinesCleared>>,
+    level_up: Res<Events<LevelUp>>,
+    game_over: Res<Events<GameOver>>,
+) {
+    // gravity is silent
+    if readers.moved.iter(&moved).any(|event| event.dx != 0) {
+        sound.play(Effect::Move);
+    }
+    if readers.rotated.iter(&rotated).next().is_some() {
+        sound.play(Effect::Rotate);
+    }
+    if readers.held.iter(&held).next().is_some() {
+        sound.play(Effect::Hold);
+    }
+    if readers.locked.iter(&locked).next().is_some() {
+        sound.play(Effect::Lock);
+    }
+    for event in readers.cleared.iter(&cleared) {
+        sound.play(match event.spin {
+            Spin::TSpin => Effect::TSpin,
+            Spin::None => Effect::LineClear(event.rows.len()),
+        });
+    }
+    if readers.level_up.iter(&level_up).next().is_some() {
+        sound.play(Effect::LevelUp);
+    }
+    if readers.game_over.iter(&game_over).next().is_some() {
+        sound.play(Effect::GameOver);
+    }
+}
+
+fn volume_system(keyboard_input: Res<Input<KeyCode>>, mut sound: ResMut<Sound>) {
+    let step = if keyboard_input.just_pressed(KeyCode::Minus) {
+        -VOLUME_STEP
+    } else if keyboard_input.just_pressed(KeyCode::Equals) {
+        VOLUME_STEP
+    } else {
+        return;
+    };
+    sound.volume = (sound.volume + step).max(0.0).min(1.0);
+    println!("volume: {:.0}%", sound.volume * 100.0);
+}
+
+fn play_sound_system(theme: Res<Theme>, mut sound: ResMut<Sound>) {
+    let volume = sound.volume;
+    let pending = std::mem::take(&mut sound.pending);
+    let sound = &mut *sound;
+    match sound.backend {
+        Backend::Null(ref mut played) => {
+            *played = pending.into_iter().map(|effect| (effect, volume)).collect();
+        }
+        Backend::Audio(_) if volume <= 0.0 => (),
+        Backend::Audio(ref device) => {
+            for effect in pending {
+                let path = theme.sound_path(effect);
+                let file =
+                    sound
+                        .files
+                        .entry(path.clone())
+                        .or_insert_with(|| match std::fs::read(&path) {
+                            Ok(content) => Some(content.into()),
+                            Err(err) => {
+                                println!("failed to load sound {}: {}", path, err);
+                                None
+                            }
+                        });
+                let content = match file {
+                    Some(content) => content.clone(),
+                    None => continue,
+                };
+                match rodio::Decoder::new(Cursor::new(content)) {
+                    Ok(source) => {
+                        let sink = Sink::new(device);
+                        sink.set_volume(volume);
+                        sink.append(source);
+                        // keeps playing until the end
+                        sink.detach();
+                    }
+                    Err(err) => println!("failed to play sound {}: {}", path, err),
+                }
+            }
+        }
+    }
+}
+
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_resource(Sound::from_args())
+            .add_system(volume_system.system())
+            .add_system_to_stage(stage::LAST, sound_event_system.system())
+            .add_system_to_stage(stage::LAST, play_sound_system.system());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Piece, PieceType};
+
+    fn app() -> App {
+        let mut builder = App::build();
+        builder
+            .add_event::<PieceMoved>()
+            .add_event::<PieceRotated>()
+            .add_event::<PieceHeld>()
+            .add_event::<PieceLocked>()
+            .add_event::<LinesCleared>()
+            .add_event::<LevelUp>()
+            .add_event::<GameOver>()
+            .add_resource(Theme::default())
+            .add_resource(Input::<KeyCode>::default())
+            .add_resource(Sound {
+                backend: Backend::Null(Vec::new()),
+                volume: 1.0,
+                pending: Vec::new(),
+                files: HashMap::new(),
+            })
+            .add_system(volume_system.system())
+            .add_system_to_stage(stage::LAST, sound_event_system.system())
+            .add_system_to_stage(stage::LAST, play_sound_system.system());
+        builder.app
+    }
+
+    fn send<T: Send + Sync + 'static>(app: &App, event: T) {
+        app.resources.get_mut::<Events<T>>().unwrap().send(event);
+    }
+
+    fn press(app: &App, key: KeyCode) {
+        app.resources
+            .get_mut::<Input<KeyCode>>()
+            .unwrap()
+            .press(key);
+    }
+
+    // runs a frame and returns the effects the null backend recorded
+    fn frame(app: &mut App) -> Vec<(Effect, f32)> {
+        app.update();
+        // the key presses of this frame are no longer new
+        app.resources.get_mut::<Input<KeyCode>>().unwrap().update();
+        match app.resources.get::<Sound>().unwrap().backend {
+            Backend::Null(ref played) => played.clone(),
+            Backend::Audio(_) => unreachable!(),
+        }
+    }
+
+    fn cleared(lines: usize, spin: Spin) -> LinesCleared {
+        LinesCleared {
+            rows: (0..lines).collect(),
+            spin,
+            combo: 1,
+            b2b: 0,
+            perfect_clear: false,
+        }
+    }
+
+    #[test]
+    fn gameplay_events() {
+        let mut app = app();
+        let piece = Piece {
+            x: 3,
+            y: 16,
+            rot: 0,
+        };
+        send(
+            &app,
+            PieceMoved {
+                piece_type: PieceType::T,
+                piece: piece.clone(),
+                dx: 0,
+                dy: -1,
+                soft_drop: false,
+            },
+        );
+        // gravity is silent
+        assert_eq!(frame(&mut app), vec![]);
+
+        send(
+            &app,
+            PieceMoved {
+                piece_type: PieceType::T,
+                piece: piece.clone(),
+                dx: 1,
+                dy: 0,
+                soft_drop: false,
+            },
+        );
+        send(
+            &app,
+            PieceLocked {
+                piece_type: PieceType::T,
+                piece,
+            },
+        );
+        send(&app, cleared(2, Spin::TSpin));
+        assert_eq!(
+            frame(&mut app),
+            vec![
+                (Effect::Move, 1.0),
+                (Effect::Lock, 1.0),
+                (Effect::TSpin, 1.0)
+            ]
+        );
+
+        // only the last frame is kept
+        assert_eq!(frame(&mut app), vec![]);
+    }
+
+    #[test]
+    fn line_clears_and_volume() {
+        let mut app = app();
+        send(&app, cleared(4, Spin::None));
+        assert_eq!(frame(&mut app), vec![(Effect::LineClear(4), 1.0)]);
+
+        press(&app, KeyCode::Minus);
+        send(&app, cleared(1, Spin::None));
+        send(&app, LevelUp { level: 1 });
+        let played = frame(&mut app);
+        assert_eq!(played.len(), 2);
+        assert_eq!(played[0].0, Effect::LineClear(1));
+        assert_eq!(played[1].0, Effect::LevelUp);
+        assert!(played.iter().all(|(_, volume)| (volume - 0.9).abs() < 1e-6));
+
+        press(&app, KeyCode::Equals);
+        send(&app, GameOver { topped_out: true });
+        assert_eq!(frame(&mut app), vec![(Effect::GameOver, 1.0)]);
+        assert_eq!(Effect::LineClear(6).name(), "line_clear_4");
+    }
+}
@@ -0,0 +1,164 @@
+use super::{
+    clear_animation::ClearAnimation,
+    field::Cell,
+    layout::{LayoutStyle, Side, CELL},
+    sound::Effect,
+};
+use bevy::{prelude::*, render::pass::ClearColor};
+
+// Look of the game: the tile set of the playfield, the hud layout and the sound effects. The sound files are looked up as
+// `<sound_dir>/<effect>.mp3`, e.g. assets/sounds/gb/line_clear_4.mp3
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub name: &'static str,
+    pub texture: &'static str,
+    // columns, rows of the tile grid
+    pub grid: (usize, usize),
+    // width and height of a tile in pixels
+    pub tile_size: f32,
+    // tile index for each cell code (see Cell::code)
+    pub tiles: [u32; 16],
+    // first tile and number of frames of the explosion (ClearAnimation::Explode). Without, the cells simply disappear
+    pub explosion: Option<(u32, u32)>,
+    pub clear_animation: ClearAnimation,
+    pub background: (f32, f32, f32),
+    // font atlas for the text of the textured renderer (see bitmap_font). Without, the text is drawn by the ui
+    pub font: Option<&'static str>,
+    pub layout: LayoutStyle,
+    pub sound_dir: &'static str,
+}
+
+pub const THEMES: [Theme; 3] = [
+    Theme {
+        name: "gb",
+        texture: "assets/textures/gb.png",
+        grid: (16, 2),
+        tile_size: 8.0,
+        tiles: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+        explosion: Some((16, 12)),
+        clear_animation: ClearAnimation::Explode,
+        background: (0.7, 0.7, 0.7),
+        font: Some("assets/textures/gb_font.png"),
+        layout: LayoutStyle {
+            next_pieces: 1,
+            stats: Side::Left,
+        },
+        sound_dir: "assets/sounds/gb",
+    },
+    Theme {
+        name: "gb_mono",
+        texture: "assets/textures/gb_mono.png",
+        grid: (16, 2),
+        tile_size: 8.0,
+        tiles: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+        explosion: Some((16, 12)),
+        clear_animation: ClearAnimation::Dissolve,
+        background: (0.7, 0.7, 0.7),
+        font: Some("assets/textures/gb_font.png"),
+        layout: LayoutStyle {
+            next_pieces: 1,
+            stats: Side::Left,
+        },
+        sound_dir: "assets/sounds/gb",
+    },
+    // a single row of tiles, the I piece has no end caps
+    Theme {
+        name: "gbc",
+        texture: "assets/textures/gbc.png",
+        grid: (10, 1),
+        tile_size: 8.0,
+        tiles: [0, 1, 8, 2, 3, 4, 5, 6, 7, 8, 8, 8, 8, 8, 8, 8],
+        explosion: None,
+        clear_animation: ClearAnimation::Flash,
+        background: (0.4, 0.74, 0.4),
+        font: None,
+        layout: LayoutStyle {
+            next_pieces: 3,
+            stats: Side::Right,
+        },
+        sound_dir: "assets/sounds/gbc",
+    },
+];
+
+impl Default for Theme {
+    fn default() -> Self {
+        THEMES[0].clone()
+    }
+}
+
+impl Theme {
+    // e.g. `bevris --theme gbc`. `--clear-animation explode|flash|slide|dissolve` overrides the line clear
+    // animation of the theme (until the theme is switched)
+    pub fn from_args() -> Self {
+        let args = std::env::args().collect::<Vec<_>>();
+        let value_of = |name: &str| {
+            args.iter()
+                .position(|arg| arg == name)
+                .and_then(|i| args.get(i + 1))
+        };
+        let mut theme = match value_of("--theme") {
+            None => Theme::default(),
+            Some(name) => match THEMES.iter().find(|theme| theme.name == name) {
+                Some(theme) => theme.clone(),
+                None => {
+                    println!("unknown theme: {}. using {}", name, THEMES[0].name);
+                    Theme::default()
+                }
+            },
+        };
+        if let Some(name) = value_of("--clear-animation") {
+            match ClearAnimation::parse(name) {
+                Some(clear_animation) => theme.clear_animation = clear_animation,
+                None => println!("unknown clear animation: {}", name),
+            }
+        }
+        theme
+    }
+
+    // scale of the tile sprites for a tile to cover a cell
+    pub fn tile_scale(&self) -> f32 {
+        CELL / self.tile_size
+    }
+
+    pub fn tile(&self, cell: Cell) -> u32 {
+        self.tiles[cell.code() as usize]
+    }
+
+    pub fn background(&self) -> Color {
+        let (r, g, b) = self.background;
+        Color::rgb(r, g, b)
+    }
+
+    pub fn sound_path(&self, effect: Effect) -> String {
+        format!("{}/{}.mp3", self.sound_dir, effect.name())
+    }
+}
+
+// F2 cycles through the themes. The renderers pick up the new tile set on their own
+fn theme_switch_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut theme: ResMut<Theme>,
+    mut clear_color: ResMut<ClearColor>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::F2) {
+        return;
+    }
+    let i = THEMES
+        .iter()
+        .position(|t| t.name == theme.name)
+        .map_or(0, |i| (i + 1) % THEMES.len());
+    *theme = THEMES[i].clone();
+    clear_color.0 = theme.background();
+    println!("theme: {}", theme.name);
+}
+
+pub struct ThemePlugin;
+
+impl Plugin for ThemePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let theme = Theme::from_args();
+        app.add_resource(ClearColor(theme.background()))
+            .add_resource(theme)
+            .add_system(theme_switch_system.system());
+    }
+}
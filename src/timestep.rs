@@ -0,0 +1,96 @@
+use bevy::prelude::*;
+use std::time::Duration;
+
+pub const TICKS_PER_SECOND: u32 = 60;
+
+// upper bound for catching up after a hitch (or while the window is being dragged around). Anything beyond
+// that is dropped, i.e. the game slows down instead of fast-forwarding through several seconds at once.
+const MAX_TICKS_PER_FRAME: u32 = 10;
+
+// Drives the game logic at a fixed rate, independent of the render frame rate. Rendering may run
+// faster or slower than the simulation, the game systems run `ticks` steps per frame to catch up.
+pub struct FixedTimestep {
+    pub step: Duration,
+    pub ticks: u32,
+    pub tick_count: u64,
+    accumulator: Duration,
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        FixedTimestep {
+            step: Duration::from_nanos(1_000_000_000 / TICKS_PER_SECOND as u64),
+            ticks: 0,
+            tick_count: 0,
+            accumulator: Duration::default(),
+        }
+    }
+}
+
+impl FixedTimestep {
+    pub fn advance(&mut self, delta: Duration) {
+        self.accumulator += delta;
+        self.ticks = 0;
+        while self.accumulator >= self.step {
+            self.accumulator -= self.step;
+            if self.ticks < MAX_TICKS_PER_FRAME {
+                self.ticks += 1;
+            }
+        }
+        self.tick_count += self.ticks as u64;
+    }
+}
+
+pub fn ticks_to_duration(ticks: u64) -> Duration {
+    Duration::from_nanos(ticks * 1_000_000_000 / TICKS_PER_SECOND as u64)
+}
+
+fn fixed_timestep_system(time: Res<Time>, mut timestep: ResMut<FixedTimestep>) {
+    timestep.advance(time.delta);
+}
+
+pub struct FixedTimestepPlugin;
+
+impl Plugin for FixedTimestepPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<FixedTimestep>()
+            .add_system_to_stage(stage::PRE_UPDATE, fixed_timestep_system.system());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accumulates_partial_steps() {
+        let mut timestep = FixedTimestep::default();
+        let half = timestep.step / 2;
+        timestep.advance(half);
+        assert_eq!(timestep.ticks, 0);
+        timestep.advance(half + Duration::from_nanos(1));
+        assert_eq!(timestep.ticks, 1);
+        timestep.advance(timestep.step * 3);
+        assert_eq!(timestep.ticks, 3);
+        assert_eq!(timestep.tick_count, 4);
+    }
+
+    #[test]
+    fn drops_ticks_after_a_hitch() {
+        let mut timestep = FixedTimestep::default();
+        timestep.advance(Duration::from_secs(1));
+        assert_eq!(timestep.ticks, MAX_TICKS_PER_FRAME);
+        // the rest of the second is not caught up later
+        timestep.advance(Duration::default());
+        assert_eq!(timestep.ticks, 0);
+        assert_eq!(timestep.tick_count, MAX_TICKS_PER_FRAME as u64);
+    }
+
+    #[test]
+    fn ticks_per_second() {
+        assert_eq!(
+            ticks_to_duration(TICKS_PER_SECOND as u64),
+            Duration::from_secs(1)
+        );
+    }
+}